@@ -0,0 +1,197 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use chrono::{Local, NaiveDateTime};
+
+const DEFAULT_BACKUP_DIR: &str = "davst_backups";
+const TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S";
+const MAX_DESCRIPTION_LEN: usize = 40;
+
+pub fn get_backup_dir(save_path: &Path, backup_dir: Option<&Path>) -> PathBuf {
+    match backup_dir {
+        Some(dir) => dir.to_path_buf(),
+        None => save_path.parent()
+            .unwrap_or(Path::new(""))
+            .join(DEFAULT_BACKUP_DIR),
+    }
+}
+
+fn save_stem(save_path: &Path) -> String {
+    save_path.file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+// Descriptions are free text from the game, keep them filename safe.
+fn clean_description(description: &str) -> String {
+    description.chars()
+        .map(|c| if c.is_alphanumeric() || c == ' ' || c == '-' || c == '_' { c } else { '_' })
+        .take(MAX_DESCRIPTION_LEN)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+// <save stem>.<timestamp>.<description>.csav, without the description when there isn't one.
+pub fn create_backup(save_path: &Path, backup_dir: &Path, description: &str) -> io::Result<PathBuf> {
+    fs::create_dir_all(backup_dir)?;
+
+    let timestamp = Local::now().format(TIMESTAMP_FORMAT).to_string();
+    let backup_path = free_backup_path(save_path, backup_dir, &timestamp, &clean_description(description));
+
+    fs::copy(save_path, &backup_path)?;
+
+    Ok(backup_path)
+}
+
+// Two writes in the same second shouldn't overwrite the first backup.
+fn free_backup_path(save_path: &Path, backup_dir: &Path, timestamp: &str, description: &str) -> PathBuf {
+    let mut name = format!("{}.{}", save_stem(save_path), timestamp);
+    if !description.is_empty() {
+        name = format!("{}.{}", name, description);
+    }
+
+    let mut backup_path = backup_dir.join(format!("{}.csav", name));
+    let mut n = 1;
+    while backup_path.exists() {
+        n += 1;
+        backup_path = backup_dir.join(format!("{} ({}).csav", name, n));
+    }
+
+    backup_path
+}
+
+// Timestamp and collision counter of a backup of the save with this prefix, None for anything else.
+fn parse_backup_name(prefix: &str, fname: &str) -> Option<(NaiveDateTime, u32)> {
+    let rest = fname.strip_prefix(prefix)?;
+    let timestamp = NaiveDateTime::parse_from_str(rest.get(..15)?, TIMESTAMP_FORMAT).ok()?;
+    let rest = rest.strip_suffix(".csav")?;
+
+    // Descriptions never have brackets, so a trailing " (n)" is always the counter.
+    let n = rest.strip_suffix(')')
+        .and_then(|rest| rest.rsplit_once(" ("))
+        .and_then(|(_, n)| n.parse().ok())
+        .unwrap_or(1);
+
+    Some((timestamp, n))
+}
+
+// Newest first, by timestamp and then by counter for backups made in the same second.
+pub fn list_backups(save_path: &Path, backup_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    if !backup_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let prefix = format!("{}.", save_stem(save_path));
+    let mut backups = Vec::new();
+
+    for entry in fs::read_dir(backup_dir)? {
+        let path = entry?.path();
+        let Some(fname) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+
+        if let Some(key) = parse_backup_name(&prefix, fname) {
+            backups.push((key, path));
+        }
+    }
+
+    backups.sort();
+    backups.reverse();
+
+    Ok(backups.into_iter().map(|(_, path)| path).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use uuid::Uuid;
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = env::temp_dir().join(format!("davst-backup-{}", Uuid::new_v4()));
+        fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn descriptions_are_made_filename_safe() {
+        assert_eq!(clean_description("Kalais: Save #5 (Minrathous)"), "Kalais_ Save _5 _Minrathous_");
+        assert_eq!(clean_description(&"a".repeat(60)).len(), MAX_DESCRIPTION_LEN);
+    }
+
+    #[test]
+    fn backups_in_the_same_second_get_a_counter() {
+        let dir = temp_dir();
+        let save_path = dir.join("0-1 save.csav");
+
+        for expected in ["0-1 save.20241111-112238.desc.csav", "0-1 save.20241111-112238.desc (2).csav", "0-1 save.20241111-112238.desc (3).csav"] {
+            let backup_path = free_backup_path(&save_path, &dir, "20241111-112238", "desc");
+            assert_eq!(backup_path, dir.join(expected));
+            fs::write(backup_path, b"").unwrap();
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn empty_description_is_left_out() {
+        let dir = temp_dir();
+        let save_path = dir.join("save.csav");
+
+        for expected in ["save.20241111-112238.csav", "save.20241111-112238 (2).csav"] {
+            let backup_path = free_backup_path(&save_path, &dir, "20241111-112238", "");
+            assert_eq!(backup_path, dir.join(expected));
+            fs::write(backup_path, b"").unwrap();
+        }
+
+        let listed = list_backups(&save_path, &dir).unwrap();
+        assert_eq!(listed, [dir.join("save.20241111-112238 (2).csav"), dir.join("save.20241111-112238.csav")]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn created_backup_is_a_copy_of_the_save() {
+        let dir = temp_dir();
+        let save_path = dir.join("save.csav");
+        fs::write(&save_path, b"save").unwrap();
+
+        let backup_path = create_backup(&save_path, &dir.join(DEFAULT_BACKUP_DIR), "Kalais: Save #5").unwrap();
+
+        assert_eq!(fs::read(&backup_path).unwrap(), b"save");
+        assert_eq!(list_backups(&save_path, &dir.join(DEFAULT_BACKUP_DIR)).unwrap(), [backup_path.as_path()]);
+        assert!(backup_path.to_string_lossy().ends_with(".Kalais_ Save _5.csav"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn backups_are_listed_newest_first() {
+        let dir = temp_dir();
+        let save_path = dir.join("save.csav");
+
+        let fnames = [
+            "save.20241111-112238.b.csav",
+            "save.20241111-112238.b (2).csav",
+            "save.20241111-112238.b (10).csav",
+            "save.20241111-112239.a.csav",
+            "save.20241111-112237.z (3).csav",
+            "other.20241111-112240.a.csav",
+            "save.not a backup.csav",
+        ];
+        for fname in fnames {
+            fs::write(dir.join(fname), b"").unwrap();
+        }
+
+        let listed: Vec<_> = list_backups(&save_path, &dir).unwrap().iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+
+        assert_eq!(listed, [
+            "save.20241111-112239.a.csav",
+            "save.20241111-112238.b (10).csav",
+            "save.20241111-112238.b (2).csav",
+            "save.20241111-112238.b.csav",
+            "save.20241111-112237.z (3).csav",
+        ]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
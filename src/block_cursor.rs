@@ -0,0 +1,28 @@
+use std::io::{self, Cursor};
+use crate::error::{BWSaveGameError, ErrorBlock};
+
+// Slices straight out of decompressed block data, so there's no limit other than the block size.
+pub(crate) fn read_bytes(c: &mut Cursor<Vec<u8>>, count: usize, block: ErrorBlock) -> Result<&[u8], BWSaveGameError> {
+    let pos = c.position() as usize;
+    let len = c.get_ref().len();
+
+    let end = pos.checked_add(count)
+        .filter(|&end| end <= len)
+        .ok_or_else(|| BWSaveGameError::Io {
+            block,
+            offset: pos as u64,
+            source: io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("tried to read {} bytes but the block is only {} bytes", count, len),
+            ),
+        })?;
+
+    c.set_position(end as u64);
+    Ok(&c.get_ref()[pos..end])
+}
+
+pub(crate) fn read_array<const N: usize>(c: &mut Cursor<Vec<u8>>, block: ErrorBlock) -> Result<[u8; N], BWSaveGameError> {
+    let mut arr = [0; N];
+    arr.copy_from_slice(read_bytes(c, N, block)?);
+    Ok(arr)
+}
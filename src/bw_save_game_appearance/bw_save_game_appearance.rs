@@ -0,0 +1,377 @@
+use std::error::Error;
+use crate::bw_save_game_appearance::enums::AppearancePart;
+use crate::bw_save_game_appearance::structs::{AppearanceChunk, AppearancePreset, BWSaveGameAppearance};
+use crate::bw_save_game_metadata_reader::structs::BWSaveGameMetadata;
+use crate::bw_save_game_client_data_reader::enums::FieldValue;
+use crate::bw_save_game_client_data_reader::structs::{BWSaveGameClientDataReader, Chunk, ChunkField};
+use crate::varint;
+
+impl BWSaveGameAppearance {
+    // Takes char_data as read by BWSaveGameClientDataReader, marker and size included.
+    pub fn from_char_data(char_data: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let mut r = BWSaveGameClientDataReader::new(char_data.to_vec());
+        r.read_chunk_tree()?;
+
+        if r.chunks.len() != 1 {
+            return Err(format!("expected one character data chunk, found {}", r.chunks.len()).into());
+        }
+
+        let chunk = &r.chunks[0];
+        if chunk.children.is_empty() && chunk.fields.is_empty() {
+            return Err("character data chunk couldn't be walked as entries".into());
+        }
+
+        let appearance = Self {
+            root: AppearanceChunk::decode(char_data, chunk),
+        };
+
+        if appearance.root.encode() != char_data {
+            return Err("character data doesn't encode back to the bytes it was read from".into());
+        }
+
+        Ok(appearance)
+    }
+
+    // Refuses anything that wouldn't read back as the same appearance, e.g. a name edited into
+    // something that isn't a valid entry name.
+    pub fn to_char_data(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let char_data = self.root.encode();
+
+        if Self::from_char_data(&char_data)? != *self {
+            return Err("appearance doesn't read back the same after encoding".into());
+        }
+
+        Ok(char_data)
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn from_json(json_data: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json_data)
+    }
+}
+
+// Bumped whenever the preset layout changes in a way older builds can't read.
+pub const PRESET_VERSION: u32 = 1;
+
+impl AppearancePreset {
+    pub fn new(meta: &BWSaveGameMetadata, char_data: &[u8]) -> Self {
+        Self {
+            version: PRESET_VERSION,
+            lineage: meta.lineage.clone(),
+            gender: meta.gender.clone(),
+            char_data: char_data.to_vec(),
+            appearance: BWSaveGameAppearance::from_char_data(char_data).ok(),
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn from_json(json_data: &str) -> Result<Self, Box<dyn Error>> {
+        let preset: Self = serde_json::from_str(json_data)?;
+
+        if preset.version > PRESET_VERSION {
+            return Err(format!(
+                "preset version {} is newer than this build supports ({})", preset.version, PRESET_VERSION,
+            ).into());
+        }
+
+        let mut r = BWSaveGameClientDataReader::new(preset.char_data.clone());
+        r.read_chunk_tree()?;
+        if r.chunks.len() != 1 {
+            return Err("preset's character data isn't a single chunk".into());
+        }
+
+        Ok(preset)
+    }
+
+    // What import-appearance injects: the decoded appearance re-encoded, so edits made to it
+    // in the preset are carried over, or the raw chunk if it couldn't be decoded.
+    pub fn import_char_data(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        match &self.appearance {
+            Some(appearance) => appearance.to_char_data(),
+            None => Ok(self.char_data.clone()),
+        }
+    }
+}
+
+// Raw bytes as a hex string, serde_json would otherwise write an array of numbers.
+pub(crate) mod hex_bytes {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&bytes.iter().map(|b| format!("{:02X}", b)).collect::<String>())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(d)?;
+        if !s.is_ascii() || s.len() % 2 != 0 {
+            return Err(D::Error::custom("not a hex string"));
+        }
+
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(D::Error::custom))
+            .collect()
+    }
+}
+
+impl AppearanceChunk {
+    fn decode(data: &[u8], chunk: &Chunk) -> Self {
+        let mut parts: Vec<(u64, AppearancePart)> = Vec::new();
+
+        for child in &chunk.children {
+            let part = if child.children.is_empty() && child.fields.is_empty() {
+                let raw_start = child.size_offset as usize;
+                AppearancePart::Unknown {
+                    tag: child.marker,
+                    name: child.name.clone(),
+                    raw: data[raw_start..child.end() as usize].to_vec(),
+                }
+            } else {
+                AppearancePart::Chunk(Self::decode(data, child))
+            };
+            parts.push((child.offset, part));
+        }
+
+        for field in &chunk.fields {
+            parts.push((field.offset, AppearancePart::from_field(data, field)));
+        }
+
+        // Children and fields are kept apart in the tree, put them back in file order.
+        parts.sort_by_key(|(offset, _)| *offset);
+
+        Self {
+            marker: chunk.marker,
+            name: chunk.name.clone(),
+            size_len: chunk.size_len,
+            parts: parts.into_iter().map(|(_, part)| part).collect(),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body: Vec<u8> = self.parts.iter()
+            .flat_map(|part| part.encode())
+            .collect();
+        body.push(0x00);
+
+        let mut bytes = vec![self.marker];
+        if let Some(name) = &self.name {
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.push(0x00);
+        }
+        bytes.extend_from_slice(&varint::encode_padded(body.len() as u32, self.size_len));
+        bytes.extend_from_slice(&body);
+
+        bytes
+    }
+}
+
+impl AppearancePart {
+    fn from_field(data: &[u8], field: &ChunkField) -> Self {
+        let start = field.value_offset as usize;
+        let raw = &data[start..start + field.value_len];
+
+        let unknown = || Self::Unknown {
+            tag: field.tag,
+            name: field.name.clone(),
+            raw: raw.to_vec(),
+        };
+
+        let (Some(name), Some(value)) = (&field.name, FieldValue::decode(field.tag, raw)) else {
+            return unknown();
+        };
+
+        // JSON has no NaN or infinity, serde would write them as null.
+        let finite = match value {
+            FieldValue::Float(v) => v.is_finite(),
+            FieldValue::Double(v) => v.is_finite(),
+            _ => true,
+        };
+        if !finite {
+            return unknown();
+        }
+
+        let lower_name = name.to_lowercase();
+        let name = name.clone();
+        let tag = field.tag;
+
+        let part = match value {
+            FieldValue::Float(value) if lower_name.contains("morph") || lower_name.contains("slider") => {
+                Self::MorphSlider { tag, name, value }
+            }
+            FieldValue::Guid(id) if lower_name.contains("hair") => Self::HairAsset { tag, name, id },
+            FieldValue::Guid(id) if lower_name.contains("tattoo") => Self::TattooAsset { tag, name, id },
+            value if lower_name.contains("colo") || lower_name.contains("tint") => Self::Colour { tag, name, value },
+            value if lower_name.contains("scar") => Self::Scar { tag, name, value },
+            _ => return unknown(),
+        };
+
+        // Strings without their terminator, bad UTF-8 or padded lengths don't decode losslessly.
+        let start = field.offset as usize;
+        if part.encode() != data[start..start + (field.value_offset - field.offset) as usize + field.value_len] {
+            return unknown();
+        }
+
+        part
+    }
+
+    fn encode_named(tag: u8, name: &str, value: &FieldValue) -> Vec<u8> {
+        let mut bytes = vec![tag];
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.push(0x00);
+        bytes.extend_from_slice(&value.encode());
+        bytes
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Self::MorphSlider { tag, name, value } => Self::encode_named(*tag, name, &FieldValue::Float(*value)),
+            Self::Colour { tag, name, value } => Self::encode_named(*tag, name, value),
+            Self::HairAsset { tag, name, id } => Self::encode_named(*tag, name, &FieldValue::Guid(*id)),
+            Self::TattooAsset { tag, name, id } => Self::encode_named(*tag, name, &FieldValue::Guid(*id)),
+            Self::Scar { tag, name, value } => Self::encode_named(*tag, name, value),
+            Self::Chunk(chunk) => chunk.encode(),
+            Self::Unknown { tag, name, raw } => {
+                let mut bytes = vec![*tag];
+                if let Some(name) = name {
+                    bytes.extend_from_slice(name.as_bytes());
+                    bytes.push(0x00);
+                }
+                bytes.extend_from_slice(raw);
+                bytes
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_data::{char_data, chunk, field, string_value};
+    use super::*;
+
+    fn round_trip(char_data: &[u8]) -> BWSaveGameAppearance {
+        let appearance = BWSaveGameAppearance::from_char_data(char_data).unwrap();
+        let from_json = BWSaveGameAppearance::from_json(&appearance.to_json().unwrap()).unwrap();
+
+        assert_eq!(from_json, appearance);
+        assert_eq!(from_json.to_char_data().unwrap(), char_data);
+        appearance
+    }
+
+    #[test]
+    fn unedited_char_data_is_byte_identical() {
+        let appearance = round_trip(&char_data(3, 4, 3));
+
+        assert_eq!(appearance.root.size_len, 3);
+        assert!(matches!(appearance.root.parts[0], AppearancePart::Chunk(_)));
+    }
+
+    #[test]
+    fn entries_that_dont_decode_cleanly_are_kept_as_read() {
+        let mut padded_len = varint::encode_padded(4, 2);
+        padded_len.extend_from_slice(b"red\0");
+
+        let record = chunk(0x82, None, &[
+            field(0x4B, "morph_slider_flagged", &0.5f32.to_le_bytes()),
+            field(0x0B, "morph_slider_nan", &f32::NAN.to_le_bytes()),
+            field(0x07, "colour_unterminated", &[&varint::encode(3)[..], b"red"].concat()),
+            field(0x07, "colour_bad_utf8", &[&varint::encode(3)[..], &[0xC3, 0x28, 0x00]].concat()),
+            field(0x07, "colour_padded", &padded_len),
+            field(0x07, "colour_plain", &string_value("blue")),
+        ].concat(), 1);
+        let data = chunk(0x82, None, &record, 2);
+
+        let appearance = round_trip(&data);
+        let AppearancePart::Chunk(record) = &appearance.root.parts[0] else {
+            panic!("record wasn't walked");
+        };
+
+        assert!(matches!(record.parts[0], AppearancePart::MorphSlider { tag: 0x4B, value: 0.5, .. }));
+        assert!(record.parts[1..5].iter().all(|p| matches!(p, AppearancePart::Unknown { .. })));
+        assert!(matches!(record.parts[5], AppearancePart::Colour { tag: 0x07, .. }));
+    }
+
+    #[test]
+    fn edited_slider_keeps_its_tag() {
+        let record = chunk(0x82, None, &field(0x4B, "morph_slider_0", &0.5f32.to_le_bytes()), 1);
+        let mut appearance = BWSaveGameAppearance::from_char_data(&chunk(0x82, None, &record, 1)).unwrap();
+
+        let AppearancePart::Chunk(record) = &mut appearance.root.parts[0] else {
+            panic!("record wasn't walked");
+        };
+        let AppearancePart::MorphSlider { value, .. } = &mut record.parts[0] else {
+            panic!("slider wasn't picked out");
+        };
+        *value = 0.25;
+
+        let expected = chunk(0x82, None, &field(0x4B, "morph_slider_0", &0.25f32.to_le_bytes()), 1);
+        assert_eq!(appearance.to_char_data().unwrap(), chunk(0x82, None, &expected, 1));
+    }
+
+    #[test]
+    fn edit_that_doesnt_read_back_is_refused() {
+        let mut appearance = BWSaveGameAppearance::from_char_data(&char_data(1, 2, 1)).unwrap();
+
+        let AppearancePart::Chunk(record) = &mut appearance.root.parts[0] else {
+            panic!("record wasn't walked");
+        };
+        let AppearancePart::MorphSlider { name, .. } = &mut record.parts[0] else {
+            panic!("slider wasn't picked out");
+        };
+        *name = "morph slider".to_string();
+
+        assert!(appearance.to_char_data().is_err());
+    }
+
+    #[test]
+    fn preset_carries_the_raw_char_data() {
+        let data = char_data(2, 3, 3);
+        let preset = AppearancePreset::new(&BWSaveGameMetadata::default(), &data);
+        let json_data = preset.to_json().unwrap();
+
+        assert!(json_data.contains(&data.iter().map(|b| format!("{:02X}", b)).collect::<String>()));
+        assert_eq!(AppearancePreset::from_json(&json_data).unwrap(), preset);
+        assert!(preset.appearance.is_some());
+    }
+
+    #[test]
+    fn edited_preset_appearance_is_what_gets_imported() {
+        let data = char_data(2, 3, 1);
+        let mut preset = AppearancePreset::new(&BWSaveGameMetadata::default(), &data);
+        assert_eq!(preset.import_char_data().unwrap(), data);
+
+        let Some(AppearancePart::Chunk(record)) = preset.appearance.as_mut().map(|a| &mut a.root.parts[1]) else {
+            panic!("record wasn't walked");
+        };
+        let AppearancePart::MorphSlider { value, .. } = &mut record.parts[0] else {
+            panic!("slider wasn't picked out");
+        };
+        *value = 0.75;
+
+        let imported = BWSaveGameAppearance::from_char_data(&preset.import_char_data().unwrap()).unwrap();
+        assert_eq!(Some(imported), preset.appearance);
+        assert_ne!(preset.import_char_data().unwrap(), data);
+    }
+
+    #[test]
+    fn broken_presets_are_refused() {
+        let preset = AppearancePreset::new(&BWSaveGameMetadata::default(), &char_data(2, 3, 1));
+        let mut value = serde_json::to_value(&preset).unwrap();
+
+        value["version"] = (PRESET_VERSION + 1).into();
+        assert!(AppearancePreset::from_json(&value.to_string()).is_err());
+
+        value["version"] = PRESET_VERSION.into();
+        value["char_data"] = "8203".into();
+        assert!(AppearancePreset::from_json(&value.to_string()).is_err());
+
+        value["char_data"] = "not hex".into();
+        assert!(AppearancePreset::from_json(&value.to_string()).is_err());
+    }
+}
@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::bw_save_game_appearance::structs::AppearanceChunk;
+use crate::bw_save_game_client_data_reader::enums::FieldValue;
+
+// Parts are picked out by field name and type, tag being the full entry tag as read so
+// its flag bits survive. Anything that wouldn't be written back byte for byte is kept
+// as it was read, raw being everything after the name (the value, or the size and
+// payload of a chunk).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AppearancePart {
+    MorphSlider {
+        tag: u8,
+        name: String,
+        value: f32,
+    },
+    Colour {
+        tag: u8,
+        name: String,
+        value: FieldValue,
+    },
+    HairAsset {
+        tag: u8,
+        name: String,
+        id: Uuid,
+    },
+    TattooAsset {
+        tag: u8,
+        name: String,
+        id: Uuid,
+    },
+    Scar {
+        tag: u8,
+        name: String,
+        value: FieldValue,
+    },
+    Chunk(AppearanceChunk),
+    Unknown {
+        tag: u8,
+        name: Option<String>,
+        raw: Vec<u8>,
+    },
+}
@@ -0,0 +1,3 @@
+pub mod bw_save_game_appearance;
+pub mod enums;
+pub mod structs;
\ No newline at end of file
@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+use crate::bw_save_game_appearance::bw_save_game_appearance::hex_bytes;
+use crate::bw_save_game_appearance::enums::AppearancePart;
+use crate::bw_save_game_metadata_reader::enums::{CharacterGender, CharacterLineage};
+
+// The character data chunk, decoded as far as the field names allow.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BWSaveGameAppearance {
+    pub root: AppearanceChunk,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppearanceChunk {
+    pub marker: u8,
+    pub name: Option<String>,
+    // Width the size was stored at, some are padded.
+    pub size_len: usize,
+    pub parts: Vec<AppearancePart>,
+}
+
+// What export-appearance writes. Lineage and gender come from the metadata of the save
+// the appearance was taken from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppearancePreset {
+    pub version: u32,
+    pub lineage: CharacterLineage,
+    pub gender: CharacterGender,
+    // The character data chunk exactly as read.
+    #[serde(with = "hex_bytes")]
+    pub char_data: Vec<u8>,
+    // Decoded from char_data, this is what import-appearance injects so edits here are kept.
+    // None if the chunk couldn't be decoded, the raw chunk is injected then.
+    pub appearance: Option<BWSaveGameAppearance>,
+}
@@ -1,117 +1,543 @@
-use std::error::Error;
-use std::io;
-use std::io::{Cursor, Read, Seek, SeekFrom};
-use crate::bw_save_game_client_data_reader::structs::BWSaveGameClientDataReader;
+use std::io::Cursor;
+use std::ops::Range;
+use crate::bw_save_game_client_data_reader::structs::{
+    BWSaveGameClientDataReader, BlockTwoLayout, Chunk, ChunkField, ContribEntry, ContribSection, ServerChunk,
+    ServerField, ServerSection,
+};
+use crate::bw_save_game_client_data_reader::enums::{AppearanceTarget, FieldValue};
+use crate::bw_save_game_metadata_reader::enums::ValueLen;
+use crate::error::{BWSaveGameError, ErrorBlock};
+use crate::varint;
+
+// Position of the character data chunk in the contribution list.
+const CHAR_DATA_INDEX: usize = 2;
 
 impl BWSaveGameClientDataReader {
-    pub(crate) fn new(data: Vec<u8>) -> Self {
+    pub fn new(data: Vec<u8>) -> Self {
         Self {
             c: Cursor::new(data),
-            buffer: vec![0; 1024 * 1024],
             char_data: Vec::new(),
             char_data_size: 0,
             char_data_offset: 0,
-            server_offset: 0,
-            server_offset_is_two_bytes: false,
-
+            layout: BlockTwoLayout::default(),
+            server: ServerSection::default(),
+            contrib: ContribSection::default(),
+            chunks: Vec::new(),
         }
     }
 
-    pub(crate) fn seek_from_start(&mut self, n: u64) -> io::Result<u64> {
-        self.c.seek(SeekFrom::Start(n))
+    fn missing_chunk(offset: u64, what: &str) -> BWSaveGameError {
+        BWSaveGameError::MissingChunk {
+            block: ErrorBlock::BlockTwo,
+            offset,
+            what: what.to_string(),
+        }
     }
 
-    fn seek_from_current(&mut self, n: i64) -> io::Result<u64> {
-        self.c.seek(SeekFrom::Current(n))
+    fn is_chunk_marker(tag: u8) -> bool {
+        matches!(tag & 0x1F, 0x01 | 0x02)
     }
-    pub(crate) fn read_u8(&mut self) -> io::Result<u8> {
-        let mut buf = [0; 1];
-        self.c.read_exact(&mut buf)?;
-        Ok(buf[0])
+
+    fn read_chunk_name(data: &[u8], offset: usize, end: usize) -> Option<String> {
+        let len = data[offset..end].iter().position(|&b| b == 0)?;
+        let name = &data[offset..offset + len];
+
+        if !name.iter().all(|b| b.is_ascii_graphic()) {
+            return None;
+        }
+
+        Some(String::from_utf8_lossy(name).to_string())
     }
-    pub(crate) fn read_bytes(&mut self, count: usize) -> io::Result<&[u8]> {
-        self.c.read_exact(&mut self.buffer[..count])?;
-        Ok(&self.buffer[..count])
+
+    fn parse_chunk(data: &[u8], offset: usize, end: usize, depth: usize) -> Result<Chunk, BWSaveGameError> {
+        let marker = data[offset];
+        if !Self::is_chunk_marker(marker) {
+            return Err(BWSaveGameError::BadChunkMarker {
+                block: ErrorBlock::BlockTwo,
+                offset: offset as u64,
+                marker,
+            });
+        }
+
+        let mut pos = offset + 1;
+
+        let name = if marker & 0x80 == 0 {
+            let name = Self::read_chunk_name(data, pos, end)
+                .ok_or(BWSaveGameError::BadChunkMarker {
+                    block: ErrorBlock::BlockTwo,
+                    offset: offset as u64,
+                    marker,
+                })?;
+            pos += name.len() + 1;
+            Some(name)
+        } else {
+            None
+        };
+
+        let size_offset = pos;
+        let (size, size_len) = varint::decode(&data[pos..end])
+            .ok_or(BWSaveGameError::SizeMismatch {
+                block: ErrorBlock::BlockTwo,
+                offset: size_offset as u64,
+                what: "chunk size isn't a valid varint".to_string(),
+                expected: varint::MAX_LEN as u64,
+                actual: (end - pos) as u64,
+            })?;
+
+        let data_offset = size_offset + size_len;
+        if data_offset + size as usize > end {
+            return Err(BWSaveGameError::SizeMismatch {
+                block: ErrorBlock::BlockTwo,
+                offset: size_offset as u64,
+                what: "chunk runs past the end of its parent".to_string(),
+                expected: (end - data_offset) as u64,
+                actual: size as u64,
+            });
+        }
+
+        let chunk_end = data_offset + size as usize;
+        let (children, fields) = Self::parse_children(data, data_offset, chunk_end, depth + 1)
+            .unwrap_or_default();
+
+        let chunk = Chunk {
+            offset: offset as u64,
+            marker,
+            name,
+            size,
+            size_offset: size_offset as u64,
+            size_len,
+            data_offset: data_offset as u64,
+            depth,
+            children,
+            fields,
+        };
+
+        Ok(chunk)
     }
 
-    fn unshift_size(bytes: &[u8]) -> u32 {
-        let mut size = 0u32;
-        let mut shift = 0;
+    // None if the payload isn't a clean run of entries ending in 0x00, the chunk is then left opaque.
+    fn parse_children(data: &[u8], start: usize, end: usize, depth: usize) -> Option<(Vec<Chunk>, Vec<ChunkField>)> {
+        let mut children = Vec::new();
+        let mut fields = Vec::new();
+        let mut pos = start;
+
+        while pos < end {
+            let tag = data[pos];
 
-        for &byte in bytes.iter() {
-            size |= ((byte & 0x7F) as u32) << shift;
-            shift += 7;
-            if byte & 0x80 == 0 {
+            if tag == 0x00 {
+                if pos + 1 != end {
+                    return None;
+                }
                 break;
             }
+
+            if Self::is_chunk_marker(tag) {
+                let child = Self::parse_chunk(data, pos, end, depth).ok()?;
+                pos = child.end() as usize;
+                children.push(child);
+                continue;
+            }
+
+            let value_len = ValueLen::from_tag(tag)?;
+            let mut value_offset = pos + 1;
+
+            let name = if tag & 0x80 == 0 {
+                let name = Self::read_chunk_name(data, value_offset, end)?;
+                value_offset += name.len() + 1;
+                Some(name)
+            } else {
+                None
+            };
+
+            let len = match value_len {
+                ValueLen::Fixed(n) => n,
+                ValueLen::Prefixed => {
+                    let (n, size_len) = varint::decode(&data[value_offset..end])?;
+                    size_len + n as usize
+                }
+            };
+
+            if value_offset + len > end {
+                return None;
+            }
+
+            fields.push(ChunkField {
+                offset: pos as u64,
+                tag,
+                name,
+                value_offset: value_offset as u64,
+                value_len: len,
+            });
+
+            pos = value_offset + len;
         }
 
-        size
+        Some((children, fields))
     }
 
-     fn seek_to_char_data(&mut self) -> Result<(), Box<dyn Error>>{
+    // Block two is one or more chunks back to back, each walked down as far as it goes.
+    pub fn read_chunk_tree(&mut self) -> Result<(), BWSaveGameError> {
+        let data = self.c.get_ref();
+        let mut chunks = Vec::new();
+        let mut pos = 0;
 
-        for _ in 0..2 {
-            let marker = self.read_u8()?;
-            if marker != 0x82 {
-                return Err("bad chunk start marker byte".into());
-            }
+        while pos < data.len() {
+            let chunk = Self::parse_chunk(data, pos, data.len(), 0)?;
+            pos = chunk.end() as usize;
+            chunks.push(chunk);
+        }
 
+        self.chunks = chunks;
 
-            let shifted_size = self.read_bytes(2)?;
+        Ok(())
+    }
 
-            let unshifted_size = Self::unshift_size(shifted_size);
+    fn decode_fields(data: &[u8], fields: &[ChunkField]) -> Result<Vec<ServerField>, BWSaveGameError> {
+        fields.iter()
+            .map(|field| {
+                let start = field.value_offset as usize;
+                let value = FieldValue::decode(field.tag, &data[start..start + field.value_len])
+                    .ok_or(BWSaveGameError::BadFieldValue {
+                        block: ErrorBlock::BlockTwo,
+                        offset: field.offset,
+                        tag: field.tag,
+                    })?;
+
+                Ok(ServerField {
+                    offset: field.offset,
+                    name: field.name.clone(),
+                    value,
+                })
+            })
+            .collect()
+    }
 
+    fn decode_chunk(data: &[u8], chunk: &Chunk) -> Result<ServerChunk, BWSaveGameError> {
+        let chunks = chunk.children.iter()
+            .map(|c| Self::decode_chunk(data, c))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // A walked chunk always has at least its end byte left over, so empty means opaque.
+        let raw = if chunk.children.is_empty() && chunk.fields.is_empty() && chunk.size > 1 {
+            Some(chunk.payload(data).to_vec())
+        } else {
+            None
+        };
+
+        let server_chunk = ServerChunk {
+            offset: chunk.offset,
+            marker: chunk.marker,
+            name: chunk.name.clone(),
+            size: chunk.size,
+            chunks,
+            fields: Self::decode_fields(data, &chunk.fields)?,
+            raw,
+        };
+
+        Ok(server_chunk)
+    }
 
-            self.seek_from_current(unshifted_size as i64)?;
+    // server_offset is the size of this chunk, everything in it is decoded to typed values.
+    pub fn read_server_section(&mut self) -> Result<(), BWSaveGameError> {
+        if self.chunks.is_empty() {
+            self.read_chunk_tree()?;
         }
 
+        let data = self.c.get_ref();
+        let server = self.chunks.first()
+            .and_then(|root| root.children.first())
+            .ok_or_else(|| Self::missing_chunk(0, "block two has no server chunk"))?;
+
+        let decoded = Self::decode_chunk(data, server)?;
+
+        self.server = ServerSection {
+            offset: server.offset,
+            size: server.size,
+            chunks: decoded.chunks,
+            fields: decoded.fields,
+        };
+
         Ok(())
     }
 
-    pub(crate) fn read_char_data(&mut self) -> Result<(), Box<dyn Error>> {
-        self.seek_from_start(12)?;
+    pub fn read_contrib_section(&mut self) -> Result<(), BWSaveGameError> {
+        if self.chunks.is_empty() {
+            self.read_chunk_tree()?;
+        }
+
+        let data = self.c.get_ref();
+        let contrib = self.chunks.first()
+            .and_then(|root| root.children.first())
+            .and_then(|server| server.children.first())
+            .ok_or_else(|| Self::missing_chunk(0, "server chunk has no contribution list"))?;
+
+        let entries = contrib.children.iter()
+            .enumerate()
+            .map(|(index, chunk)| {
+                Ok(ContribEntry {
+                    index,
+                    is_char_data: index == CHAR_DATA_INDEX,
+                    chunk: Self::decode_chunk(data, chunk)?,
+                })
+            })
+            .collect::<Result<Vec<_>, BWSaveGameError>>()?;
+
+        self.contrib = ContribSection {
+            offset: contrib.offset,
+            size: contrib.size,
+            entries,
+        };
+
+        Ok(())
+    }
+
+    // Block two starts with a root chunk holding "server", which holds the contribution list.
+    // The character data is the third chunk in that list.
+    pub fn read_char_data(&mut self) -> Result<(), BWSaveGameError> {
+        self.read_chunk_tree()?;
+
+        let layout = BlockTwoLayout::from_chunks(&self.chunks)?;
+
+        let contrib = &self.chunks[0].children[0].children[0];
+        let char_chunk = contrib.children.get(CHAR_DATA_INDEX)
+            .ok_or_else(|| Self::missing_chunk(contrib.offset, "contribution list has no character data chunk"))?;
+
+        if char_chunk.marker != 0x82 {
+            return Err(BWSaveGameError::BadChunkMarker {
+                block: ErrorBlock::BlockTwo,
+                offset: char_chunk.offset,
+                marker: char_chunk.marker,
+            });
+        }
+
+        self.char_data = self.c.get_ref()[char_chunk.offset as usize..char_chunk.end() as usize].to_vec();
+        self.char_data_offset = char_chunk.offset;
+        self.char_data_size = char_chunk.size;
+        self.layout = layout;
+
+        Ok(())
+    }
 
+    // Whose record this is going by its name, Some only for a named record that says.
+    fn record_owner(record: &Chunk) -> Option<AppearanceTarget> {
+        let name = record.name.as_ref()?.to_lowercase();
 
-        let arr = self.read_bytes(4)?;
+        if name.contains("rook") {
+            Some(AppearanceTarget::Rook)
+        } else if name.contains("inquisitor") {
+            Some(AppearanceTarget::Inquisitor)
+        } else {
+            None
+        }
+    }
 
-        let is_two_bytes = arr[3] == 0x63;
+    // Slider fields are what every appearance record has, anywhere in the record. The names are
+    // an assumption that hasn't been checked against a real save, see the README.
+    fn has_morph_sliders(chunk: &Chunk) -> bool {
+        chunk.fields.iter()
+            .filter_map(|f| f.name.as_ref())
+            .map(|name| name.to_lowercase())
+            .any(|name| name.contains("morph") || name.contains("slider"))
+            || chunk.children.iter().any(Self::has_morph_sliders)
+    }
 
-        let server_offset_len = if is_two_bytes { 2 } else { 3 };
+    // Range of one character's record inside char_data, which has to be a whole character data chunk.
+    // Records are told apart by name where they have one and by position (Rook's first) where they don't,
+    // and both have to look like appearance records.
+    pub fn character_range(char_data: &[u8], target: AppearanceTarget) -> Result<Range<usize>, BWSaveGameError> {
+        let mut r = Self::new(char_data.to_vec());
+        r.read_chunk_tree()?;
+
+        let records = &r.chunks.first()
+            .ok_or_else(|| Self::missing_chunk(0, "character data is empty"))?
+            .children;
+
+        if records.len() != 2 {
+            return Err(Self::missing_chunk(0, &format!(
+                "expected Rook's and the Inquisitor's appearance records, found {} chunk(s)", records.len(),
+            )));
+        }
 
-        // 0x02 server
-        let unshifted_server_offset = Self::unshift_size(&arr[..server_offset_len]);
+        let positions = [AppearanceTarget::Rook, AppearanceTarget::Inquisitor];
+        let mut owners = Vec::new();
 
-        self.seek_from_current(14)?;
+        for (record, position) in records.iter().zip(positions) {
+            if !Self::has_morph_sliders(record) {
+                return Err(BWSaveGameError::BadValue {
+                    block: ErrorBlock::BlockTwo,
+                    offset: record.offset,
+                    what: "chunk doesn't look like an appearance record, it has no morph sliders".to_string(),
+                });
+            }
 
-        if !is_two_bytes {
-            self.seek_from_current(2)?;
+            let owner = match (&record.name, Self::record_owner(record)) {
+                (None, _) => position,
+                (Some(_), Some(owner)) => owner,
+                (Some(name), None) => {
+                    return Err(BWSaveGameError::BadValue {
+                        block: ErrorBlock::BlockTwo,
+                        offset: record.offset,
+                        what: format!("appearance record {:?} isn't Rook's or the Inquisitor's", name),
+                    });
+                }
+            };
+            owners.push(owner);
         }
 
-        self.seek_to_char_data()?;
-        let char_data_offset = self.c.stream_position()?;
+        if owners[0] == owners[1] {
+            return Err(Self::missing_chunk(0, &format!("both appearance records are {:?}'s", owners[0])));
+        }
 
-        let marker = self.read_u8()?;
-        if marker != 0x82 {
-            return Err("bad chunk start marker byte".into());
+        if target == AppearanceTarget::Both {
+            return Ok(0..char_data.len());
         }
 
-        let shifted_size = self.read_bytes(3)?;
+        let record = &records[owners.iter().position(|&o| o == target).unwrap()];
+
+        Ok(record.offset as usize..record.end() as usize)
+    }
+}
+
+impl Chunk {
+    pub fn end(&self) -> u64 {
+        self.data_offset + self.size as u64
+    }
 
-        let unshifted_size = Self::unshift_size(shifted_size);
-        self.seek_from_current(-4)?;
+    pub fn payload<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        &data[self.data_offset as usize..self.end() as usize]
+    }
+}
 
-        let unshifted_size_usize = (unshifted_size+4) as usize;
-        let char_data = self.read_bytes(unshifted_size_usize)?;
+#[cfg(test)]
+mod tests {
+    use crate::test_data::{appearance_record, block_two, char_data, chunk, field};
+    use super::*;
 
-        self.char_data = char_data.to_vec();
-        self.char_data_offset = char_data_offset;
-        self.char_data_size = unshifted_size;
-        self.server_offset = unshifted_server_offset;
-        self.server_offset_is_two_bytes = is_two_bytes;
+    fn read_tree(data: Vec<u8>) -> Result<Vec<Chunk>, BWSaveGameError> {
+        let mut r = BWSaveGameClientDataReader::new(data);
+        r.read_chunk_tree()?;
+        Ok(r.chunks)
+    }
 
-        Ok(())
+    #[test]
+    fn walks_nested_chunks_and_fields() {
+        let data = block_two(&char_data(1, 3, 3), 3);
+        let chunks = read_tree(data.clone()).unwrap();
+
+        assert_eq!(chunks.len(), 1);
+        let root = &chunks[0];
+        assert_eq!((root.marker, root.name.as_deref(), root.depth), (0x82, None, 0));
+        assert_eq!(root.end() as usize, data.len());
+
+        let server = &root.children[0];
+        assert_eq!(server.name.as_deref(), Some("server"));
+        assert_eq!(root.children[1].name.as_deref(), Some("client"));
+
+        let contrib = &server.children[0];
+        assert_eq!((contrib.marker, contrib.name.as_deref()), (0x01, Some("contribution")));
+        assert_eq!(contrib.children.len(), 4);
+
+        let quest = &contrib.children[0];
+        assert_eq!(quest.depth, 3);
+        assert_eq!(quest.fields.len(), 2);
+        assert_eq!(quest.fields[1].name.as_deref(), Some("zone"));
+        let zone = &quest.fields[1];
+        assert_eq!(&data[zone.value_offset as usize..][..zone.value_len], b"\x0BMinrathous\x00");
+
+        let records = &contrib.children[CHAR_DATA_INDEX].children;
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].fields.len(), 7);
     }
 
+    #[test]
+    fn reads_sizes_at_their_stored_width() {
+        let data = chunk(0x82, None, &field(0x08, "level", &6u32.to_le_bytes()), 4);
+        let chunks = read_tree(data.clone()).unwrap();
+
+        assert_eq!(chunks[0].size_offset, 1);
+        assert_eq!(chunks[0].size_len, 4);
+        assert_eq!(chunks[0].data_offset, 5);
+        assert_eq!(chunks[0].payload(&data), &data[5..]);
+    }
+
+    #[test]
+    fn payload_that_isnt_entries_is_left_opaque() {
+        let data = chunk(0x82, None, &[0xFE, 0xFE, 0xFE], 1);
+        let chunks = read_tree(data).unwrap();
+
+        assert!(chunks[0].children.is_empty());
+        assert!(chunks[0].fields.is_empty());
+        assert_eq!(chunks[0].size, 4);
+    }
 
+    #[test]
+    fn chunk_running_past_its_parent_is_a_size_mismatch() {
+        let mut data = chunk(0x82, None, &[], 1);
+        data.extend_from_slice(&[0x82]);
+        data.extend_from_slice(&varint::encode_padded(10, 1));
+
+        let err = read_tree(data).unwrap_err();
+        assert!(matches!(err, BWSaveGameError::SizeMismatch { block: ErrorBlock::BlockTwo, offset: 4, .. }));
+    }
+
+    #[test]
+    fn bad_top_level_marker_is_an_error() {
+        let err = read_tree(vec![0x08, 0x00]).unwrap_err();
+        assert!(matches!(err, BWSaveGameError::BadChunkMarker { offset: 0, marker: 0x08, .. }));
+    }
+
+    #[test]
+    fn finds_the_character_data_chunk() {
+        let char_chunk = char_data(1, 3, 3);
+        let data = block_two(&char_chunk, 3);
+
+        let mut r = BWSaveGameClientDataReader::new(data.clone());
+        r.read_char_data().unwrap();
+
+        assert_eq!(r.char_data, char_chunk);
+        assert_eq!(&data[r.char_data_offset as usize..][..char_chunk.len()], char_chunk.as_slice());
+    }
+
+    #[test]
+    fn character_records_are_found_by_position() {
+        let char_chunk = char_data(1, 3, 1);
+        let records = &read_tree(char_chunk.clone()).unwrap()[0].children;
+
+        let rook = BWSaveGameClientDataReader::character_range(&char_chunk, AppearanceTarget::Rook).unwrap();
+        let inquisitor = BWSaveGameClientDataReader::character_range(&char_chunk, AppearanceTarget::Inquisitor).unwrap();
+
+        assert_eq!(rook, records[0].offset as usize..records[0].end() as usize);
+        assert_eq!(inquisitor, records[1].offset as usize..records[1].end() as usize);
+    }
+
+    #[test]
+    fn named_character_records_are_found_by_name() {
+        let inquisitor_record = appearance_record(Some("inquisitor"), 1, 2);
+        let rook_record = appearance_record(Some("Rook"), 2, 3);
+        let char_chunk = chunk(0x82, None, &[inquisitor_record.clone(), rook_record.clone()].concat(), 1);
+
+        let rook = BWSaveGameClientDataReader::character_range(&char_chunk, AppearanceTarget::Rook).unwrap();
+        let inquisitor = BWSaveGameClientDataReader::character_range(&char_chunk, AppearanceTarget::Inquisitor).unwrap();
+
+        assert_eq!(&char_chunk[rook], rook_record.as_slice());
+        assert_eq!(&char_chunk[inquisitor], inquisitor_record.as_slice());
+    }
+
+    #[test]
+    fn records_that_cant_be_told_apart_are_refused() {
+        let cases = [
+            // Both named Rook's.
+            [appearance_record(Some("rook"), 1, 2), appearance_record(Some("rook_alt"), 2, 2)],
+            // Named after someone else.
+            [appearance_record(Some("companion"), 1, 2), appearance_record(None, 2, 2)],
+            // Not an appearance record.
+            [chunk(0x82, None, &field(0x08, "gold", &5u32.to_le_bytes()), 1), appearance_record(None, 2, 2)],
+        ];
+
+        for records in cases {
+            let char_chunk = chunk(0x82, None, &records.concat(), 1);
+            assert!(BWSaveGameClientDataReader::character_range(&char_chunk, AppearanceTarget::Rook).is_err());
+            assert!(BWSaveGameClientDataReader::character_range(&char_chunk, AppearanceTarget::Both).is_err());
+        }
+    }
 }
\ No newline at end of file
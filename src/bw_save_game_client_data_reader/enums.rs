@@ -0,0 +1,110 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::varint;
+
+// Which character's appearance record to carry over. The character data chunk holds
+// Rook's record first and the Inquisitor's second, unless they're named otherwise.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum AppearanceTarget {
+    Rook,
+    Inquisitor,
+    #[default]
+    Both,
+}
+
+// Decoded value of a chunk field, one variant per value type.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FieldValue {
+    Bool(bool),
+    String(String),
+    Int32(i32),
+    Int64(i64),
+    Float(f32),
+    Double(f64),
+    Guid(Uuid),
+    Sha1([u8; 20]),
+    Blob(Vec<u8>),
+}
+
+impl FieldValue {
+    // Bytes are the whole value, including the length for strings and blobs.
+    pub fn decode(tag: u8, bytes: &[u8]) -> Option<Self> {
+        let value = match tag & 0x1F {
+            0x06 => Self::Bool(*bytes.first()? != 0),
+            0x07 => {
+                let (len, len_len) = varint::decode(bytes)?;
+                let s = bytes.get(len_len..len_len + len as usize)?;
+                let s = s.strip_suffix(&[0]).unwrap_or(s);
+                Self::String(String::from_utf8_lossy(s).to_string())
+            }
+            0x08 => Self::Int32(i32::from_le_bytes(bytes.try_into().ok()?)),
+            0x09 => Self::Int64(i64::from_le_bytes(bytes.try_into().ok()?)),
+            0x0B => Self::Float(f32::from_le_bytes(bytes.try_into().ok()?)),
+            0x0C => Self::Double(f64::from_le_bytes(bytes.try_into().ok()?)),
+            0x0F => Self::Guid(Uuid::from_bytes_le(bytes.try_into().ok()?)),
+            0x10 => Self::Sha1(bytes.try_into().ok()?),
+            0x13 => {
+                let (len, len_len) = varint::decode(bytes)?;
+                Self::Blob(bytes.get(len_len..len_len + len as usize)?.to_vec())
+            }
+            _ => return None,
+        };
+
+        Some(value)
+    }
+
+    // Low 5 bits of the tag for this value.
+    pub fn type_code(&self) -> u8 {
+        match self {
+            Self::Bool(_) => 0x06,
+            Self::String(_) => 0x07,
+            Self::Int32(_) => 0x08,
+            Self::Int64(_) => 0x09,
+            Self::Float(_) => 0x0B,
+            Self::Double(_) => 0x0C,
+            Self::Guid(_) => 0x0F,
+            Self::Sha1(_) => 0x10,
+            Self::Blob(_) => 0x13,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Self::Bool(v) => vec![*v as u8],
+            Self::String(v) => {
+                let mut bytes = varint::encode(v.len() as u32 + 1);
+                bytes.extend_from_slice(v.as_bytes());
+                bytes.push(0x00);
+                bytes
+            }
+            Self::Int32(v) => v.to_le_bytes().to_vec(),
+            Self::Int64(v) => v.to_le_bytes().to_vec(),
+            Self::Float(v) => v.to_le_bytes().to_vec(),
+            Self::Double(v) => v.to_le_bytes().to_vec(),
+            Self::Guid(v) => v.to_bytes_le().to_vec(),
+            Self::Sha1(v) => v.to_vec(),
+            Self::Blob(v) => {
+                let mut bytes = varint::encode(v.len() as u32);
+                bytes.extend_from_slice(v);
+                bytes
+            }
+        }
+    }
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool(v) => write!(f, "{}", v),
+            Self::String(v) => write!(f, "{:?}", v),
+            Self::Int32(v) => write!(f, "{}", v),
+            Self::Int64(v) => write!(f, "{}", v),
+            Self::Float(v) => write!(f, "{}", v),
+            Self::Double(v) => write!(f, "{}", v),
+            Self::Guid(v) => write!(f, "{}", v.braced()),
+            Self::Sha1(v) => v.iter().try_for_each(|b| write!(f, "{:02X}", b)),
+            Self::Blob(v) => write!(f, "{} byte blob", v.len()),
+        }
+    }
+}
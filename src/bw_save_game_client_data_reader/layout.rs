@@ -0,0 +1,284 @@
+use std::error::Error;
+use std::ops::Range;
+use crate::bw_save_game_client_data_reader::structs::{BWSaveGameClientDataReader, BlockTwoLayout, Chunk, LayoutEntry};
+use crate::error::{BWSaveGameError, ErrorBlock};
+use crate::varint;
+
+impl BlockTwoLayout {
+    pub fn from_chunks(chunks: &[Chunk]) -> Result<Self, BWSaveGameError> {
+        let missing_chunk = |offset: u64, what: &str| BWSaveGameError::MissingChunk {
+            block: ErrorBlock::BlockTwo,
+            offset,
+            what: what.to_string(),
+        };
+
+        let root = chunks.first()
+            .ok_or_else(|| missing_chunk(0, "block two has no root chunk"))?;
+        let server = root.children.first()
+            .ok_or_else(|| missing_chunk(root.offset, "block two has no server chunk"))?;
+        let contrib = server.children.first()
+            .ok_or_else(|| missing_chunk(server.offset, "server chunk has no contribution list"))?;
+
+        let layout = Self {
+            root: LayoutEntry::from_chunk(root),
+            server: LayoutEntry::from_chunk(server),
+            contrib: LayoutEntry::from_chunk(contrib),
+            chunks: chunks.to_vec(),
+        };
+
+        Ok(layout)
+    }
+
+    pub fn server_offset(&self) -> u32 {
+        self.server.size
+    }
+
+    pub fn contrib_offset(&self) -> u32 {
+        self.contrib.size
+    }
+
+    // Every chunk whose payload holds the range, outermost first. A range can cover whole
+    // chunks, but not cut into a chunk's marker, name or size, or only part of one.
+    fn enclosing_chunks(&self, range: &Range<usize>) -> Result<Vec<&Chunk>, Box<dyn Error>> {
+        let (start, end) = (range.start as u64, range.end as u64);
+
+        let mut path: Vec<&Chunk> = Vec::new();
+        let mut siblings = &self.chunks;
+
+        loop {
+            // An empty range right at a chunk's end is an insert after it, not into it.
+            let inside = siblings.iter()
+                .find(|c| c.data_offset <= start && end <= c.end() && start < c.end());
+
+            for chunk in siblings {
+                let covered = start <= chunk.offset && chunk.end() <= end;
+                let disjoint = end <= chunk.offset || chunk.end() <= start;
+                let inside_this = inside.is_some_and(|c| std::ptr::eq(c, chunk));
+
+                if !covered && !disjoint && !inside_this {
+                    return Err(format!(
+                        "{:#X}..{:#X} cuts into the chunk at {:#X}", start, end, chunk.offset,
+                    ).into());
+                }
+            }
+
+            match inside {
+                Some(chunk) => {
+                    path.push(chunk);
+                    siblings = &chunk.children;
+                }
+                None => break,
+            }
+        }
+
+        if path.is_empty() {
+            return Err(format!("{:#X}..{:#X} is outside the root chunk", start, end).into());
+        }
+
+        Ok(path)
+    }
+
+    // Replaces any range inside the root chunk and rewrites every size that encloses it,
+    // innermost first so each one takes in the width changes of the ones inside it.
+    // The layout is then rebuilt from the new data.
+    pub fn splice(&mut self, data: &mut Vec<u8>, range: Range<usize>, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        let sizes: Vec<(u64, u32)> = self.enclosing_chunks(&range)?
+            .iter()
+            .map(|c| (c.size_offset, c.size))
+            .collect();
+
+        let mut delta = bytes.len() as i64 - range.len() as i64;
+        data.splice(range, bytes.iter().copied());
+
+        // Outer size fields sit before inner ones, so rewriting from the inside out never moves
+        // a size field that's still to be rewritten.
+        for (size_offset, size) in sizes.into_iter().rev() {
+            let size = u32::try_from(size as i64 + delta)
+                .map_err(|_| format!("chunk size at {:#X} is out of range", size_offset))?;
+
+            delta += varint::rewrite(data, size_offset as usize, size)
+                .ok_or("chunk size isn't a valid varint")?;
+        }
+
+        let mut r = BWSaveGameClientDataReader::new(data.clone());
+        r.read_chunk_tree()?;
+        *self = Self::from_chunks(&r.chunks)?;
+
+        Ok(())
+    }
+}
+
+impl LayoutEntry {
+    fn from_chunk(chunk: &Chunk) -> Self {
+        Self {
+            size_offset: chunk.size_offset,
+            size_len: chunk.size_len,
+            size: chunk.size,
+        }
+    }
+
+    pub fn data_offset(&self) -> u64 {
+        self.size_offset + self.size_len as u64
+    }
+
+    pub fn end(&self) -> u64 {
+        self.data_offset() + self.size as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_data::{block_two, char_data, chunk, field};
+    use super::*;
+
+    fn read(data: &[u8]) -> BWSaveGameClientDataReader {
+        let mut r = BWSaveGameClientDataReader::new(data.to_vec());
+        r.read_char_data().unwrap();
+        r
+    }
+
+    // The one record inside the character data chunk.
+    fn record(r: &BWSaveGameClientDataReader) -> &Chunk {
+        &r.chunks[0].children[0].children[0].children[2].children[0]
+    }
+
+    fn small_block_two() -> Vec<u8> {
+        let record = chunk(0x82, None, &field(0x08, "level", &6u32.to_le_bytes()), 1);
+        block_two(&chunk(0x82, None, &record, 1), 3)
+    }
+
+    fn blob(len: usize) -> Vec<u8> {
+        let mut value = varint::encode(len as u32);
+        value.extend(vec![0xAB; len]);
+        field(0x13, "blob", &value)
+    }
+
+    #[test]
+    fn splice_deep_inside_char_data_grows_every_enclosing_size() {
+        let mut data = small_block_two();
+        let r = read(&data);
+        let (old_record_size, old_record_size_len) = (record(&r).size, record(&r).size_len);
+
+        // Before the record's end byte, big enough to push its size past one byte.
+        let at = record(&r).end() as usize - 1;
+        let bytes = blob(120);
+        let mut layout = r.layout.clone();
+        layout.splice(&mut data, at..at, &bytes).unwrap();
+
+        let new_r = read(&data);
+        let new_record = record(&new_r);
+        assert_eq!(new_record.size, old_record_size + bytes.len() as u32);
+        assert_eq!(new_record.size_len, old_record_size_len + 1);
+        assert_eq!(new_record.fields.len(), 2);
+        assert_eq!(new_record.fields[1].name.as_deref(), Some("blob"));
+        assert_eq!(new_r.chunks[0].end() as usize, data.len());
+
+        assert_eq!(layout.root, new_r.layout.root);
+        assert_eq!(layout.server, new_r.layout.server);
+        assert_eq!(layout.contrib, new_r.layout.contrib);
+        assert_eq!(layout.contrib_offset(), r.layout.contrib_offset() + bytes.len() as u32 + 2);
+    }
+
+    #[test]
+    fn splice_that_shrinks_a_size_keeps_its_width() {
+        let big_record = chunk(0x82, None, &blob(120), 1);
+        let mut data = block_two(&chunk(0x82, None, &big_record, 1), 3);
+        let r = read(&data);
+        assert_eq!(record(&r).size_len, 2);
+
+        let field = &record(&r).fields[0];
+        let range = field.offset as usize..field.value_offset as usize + field.value_len;
+        let mut layout = r.layout.clone();
+        layout.splice(&mut data, range, &[]).unwrap();
+
+        let new_r = read(&data);
+        assert_eq!(record(&new_r).size, 1);
+        assert_eq!(record(&new_r).size_len, 2);
+        assert_eq!(new_r.chunks[0].end() as usize, data.len());
+        assert_eq!(layout.server, new_r.layout.server);
+    }
+
+    #[test]
+    fn zero_delta_splice_on_padded_sizes_is_byte_identical() {
+        let data = block_two(&char_data(1, 3, 3), 3);
+        let r = read(&data);
+        let char_range = r.char_data_offset as usize..r.char_data_offset as usize + r.char_data.len();
+
+        let mut new_data = data.clone();
+        let mut layout = r.layout.clone();
+        layout.splice(&mut new_data, char_range.clone(), &r.char_data).unwrap();
+        assert_eq!(new_data, data);
+
+        // Same length, so only the spliced bytes change.
+        let other = char_data(2, 3, 3);
+        layout.splice(&mut new_data, char_range.clone(), &other).unwrap();
+        assert_eq!(new_data[..char_range.start], data[..char_range.start]);
+        assert_eq!(new_data[char_range.end..], data[char_range.end..]);
+        assert_eq!((layout.root, layout.server, layout.contrib), (r.layout.root, r.layout.server, r.layout.contrib));
+    }
+
+    #[test]
+    fn splice_undone_leaves_padded_sizes_as_they_were() {
+        let data = block_two(&char_data(1, 3, 3), 3);
+        let r = read(&data);
+        let at = record(&r).end() as usize - 1;
+        let bytes = blob(300);
+
+        let mut new_data = data.clone();
+        let mut layout = r.layout.clone();
+        layout.splice(&mut new_data, at..at, &bytes).unwrap();
+        layout.splice(&mut new_data, at..at + bytes.len(), &[]).unwrap();
+
+        assert_eq!(new_data, data);
+    }
+
+    #[test]
+    fn splice_in_another_server_child() {
+        let mut data = small_block_two();
+        let r = read(&data);
+        let quest = &r.chunks[0].children[0].children[0].children[0];
+
+        let at = quest.end() as usize - 1;
+        let mut layout = r.layout.clone();
+        layout.splice(&mut data, at..at, &blob(200)).unwrap();
+
+        let new_r = read(&data);
+        let new_quest = &new_r.chunks[0].children[0].children[0].children[0];
+        assert_eq!(new_quest.fields.len(), 3);
+        assert_eq!(new_r.char_data, r.char_data);
+    }
+
+    #[test]
+    fn replacing_whole_chunks_is_allowed() {
+        let mut data = small_block_two();
+        let r = read(&data);
+        let char_range = r.char_data_offset as usize..r.char_data_offset as usize + r.char_data.len();
+
+        let new_char_data = chunk(0x82, None, &chunk(0x82, None, &blob(150), 1), 1);
+        let mut layout = r.layout.clone();
+        layout.splice(&mut data, char_range, &new_char_data).unwrap();
+
+        assert_eq!(read(&data).char_data, new_char_data);
+    }
+
+    #[test]
+    fn splice_that_cuts_into_a_chunk_header_is_refused() {
+        let mut data = small_block_two();
+        let r = read(&data);
+        let size_offset = record(&r).size_offset as usize;
+
+        let mut layout = r.layout.clone();
+        assert!(layout.splice(&mut data, size_offset..size_offset + 1, &[0x10]).is_err());
+        assert!(layout.splice(&mut data, size_offset - 1..size_offset + 3, &[]).is_err());
+    }
+
+    #[test]
+    fn splice_outside_the_root_is_refused() {
+        let mut data = small_block_two();
+        let len = data.len();
+        let mut layout = read(&data).layout.clone();
+
+        assert!(layout.splice(&mut data, len..len, &[0x00]).is_err());
+        assert!(layout.splice(&mut data, 0..1, &[0x82]).is_err());
+    }
+}
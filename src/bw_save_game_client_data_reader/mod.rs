@@ -1,2 +1,4 @@
 pub mod bw_save_game_client_data_reader;
-pub(crate) mod structs;
\ No newline at end of file
+pub mod enums;
+pub mod layout;
+pub mod structs;
\ No newline at end of file
@@ -1,11 +1,101 @@
 use std::io::Cursor;
+use serde::{Deserialize, Serialize};
+use crate::bw_save_game_client_data_reader::enums::FieldValue;
 
 pub struct BWSaveGameClientDataReader {
     pub(crate) c: Cursor<Vec<u8>>,
-    pub(crate) buffer: Vec<u8>,
-    pub(crate) char_data: Vec<u8>,
-    pub(crate) char_data_offset: u64,
-    pub(crate) char_data_size: u32,
-    pub(crate) server_offset: u32,
-    pub(crate) server_offset_is_two_bytes: bool,
+    pub char_data: Vec<u8>,
+    pub char_data_offset: u64,
+    pub char_data_size: u32,
+    pub layout: BlockTwoLayout,
+    pub server: ServerSection,
+    pub contrib: ContribSection,
+    pub chunks: Vec<Chunk>,
+}
+
+// A list or object entry, the marker is the entry's tag byte. Offsets are into block two.
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub offset: u64,
+    pub marker: u8,
+    pub name: Option<String>,
+    pub size: u32,
+    pub size_offset: u64,
+    pub size_len: usize,
+    pub data_offset: u64,
+    pub depth: usize,
+    // Both are empty if the payload couldn't be walked as entries.
+    pub children: Vec<Chunk>,
+    pub fields: Vec<ChunkField>,
+}
+
+// Any other entry inside a chunk.
+#[derive(Debug, Clone)]
+pub struct ChunkField {
+    pub offset: u64,
+    pub tag: u8,
+    pub name: Option<String>,
+    pub value_offset: u64,
+    pub value_len: usize,
+}
+
+// The size fields that enclose the contribution list. server_offset is the server chunk's
+// size and contrib_offset the contribution list's. The whole tree is kept so a splice
+// anywhere in block two can find every size around it.
+#[derive(Debug, Default, Clone)]
+pub struct BlockTwoLayout {
+    pub root: LayoutEntry,
+    pub server: LayoutEntry,
+    pub contrib: LayoutEntry,
+    pub(crate) chunks: Vec<Chunk>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LayoutEntry {
+    pub size_offset: u64,
+    pub size_len: usize,
+    pub size: u32,
+}
+
+// The "server" chunk decoded down to typed values.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ServerSection {
+    pub offset: u64,
+    pub size: u32,
+    pub chunks: Vec<ServerChunk>,
+    pub fields: Vec<ServerField>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerChunk {
+    pub offset: u64,
+    pub marker: u8,
+    pub name: Option<String>,
+    pub size: u32,
+    pub chunks: Vec<ServerChunk>,
+    pub fields: Vec<ServerField>,
+    // Payload of chunks that couldn't be walked as entries.
+    pub raw: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerField {
+    pub offset: u64,
+    pub name: Option<String>,
+    pub value: FieldValue,
+}
+
+// The contribution list inside the server chunk, contrib_offset is its size.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ContribSection {
+    pub offset: u64,
+    pub size: u32,
+    pub entries: Vec<ContribEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContribEntry {
+    pub index: usize,
+    pub is_char_data: bool,
+    pub chunk: ServerChunk,
 }
\ No newline at end of file
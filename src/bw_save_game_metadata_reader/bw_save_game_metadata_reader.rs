@@ -1,174 +1,267 @@
-use std::error::Error;
-use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::io::Cursor;
 use chrono::{DateTime, Utc};
 use uuid::Uuid;
 use crate::bw_save_game_metadata_reader::structs::*;
 use crate::bw_save_game_metadata_reader::enums::*;
+use crate::block_cursor;
+use crate::error::{BWSaveGameError, ErrorBlock};
+use crate::varint;
 
 impl BWSaveGameMetadataReader {
-    pub(crate) fn new(data: Vec<u8>) -> Self {
+    pub fn new(data: Vec<u8>) -> Self {
         Self {
             c: Cursor::new(data),
-            buffer: vec![0; 1024 * 1024],
-            metadata: BWSaveGameMetadata::default()
+            metadata: BWSaveGameMetadata::default(),
+            prefix: Vec::new(),
+            entries: Vec::new(),
         }
     }
 
-    pub(crate) fn read_bytes(&mut self, count: usize) -> io::Result<&[u8]> {
-        self.c.read_exact(&mut self.buffer[..count])?;
-        Ok(&self.buffer[..count])
+    pub(crate) fn read_bytes(&mut self, count: usize) -> Result<&[u8], BWSaveGameError> {
+        block_cursor::read_bytes(&mut self.c, count, ErrorBlock::BlockOne)
     }
 
-    pub(crate) fn read_string_of_len(&mut self, n : usize) -> Result<String, Box<dyn Error>> {
-        let buf = self.read_bytes(n)?;
-        let s = String::from_utf8(buf.to_vec())?;
-        Ok(s)
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], BWSaveGameError> {
+        block_cursor::read_array(&mut self.c, ErrorBlock::BlockOne)
     }
 
-    fn read_guid(&mut self) -> Result<Uuid, Box<dyn Error>> {
-        let guid_bytes = self.read_bytes(16)?;
-        let guid = Uuid::from_bytes_le(guid_bytes.try_into()?);
-        Ok(guid)
+    fn bad_value(offset: u64, what: String) -> BWSaveGameError {
+        BWSaveGameError::BadValue {
+            block: ErrorBlock::BlockOne,
+            offset,
+            what,
+        }
     }
 
-    pub(crate) fn seek_from_start(&mut self, n: u64) -> io::Result<u64> {
-        self.c.seek(SeekFrom::Start(n))
+    fn utf8_string(offset: u64, buf: Vec<u8>) -> Result<String, BWSaveGameError> {
+        String::from_utf8(buf)
+            .map_err(|e| Self::bad_value(offset, format!("string isn't UTF-8: {}", e)))
     }
 
-    fn seek_from_current(&mut self, n: i64) -> io::Result<u64> {
-        self.c.seek(SeekFrom::Current(n))
+    pub(crate) fn read_string_of_len(&mut self, n : usize) -> Result<String, BWSaveGameError> {
+        let offset = self.c.position();
+        let buf = self.read_bytes(n)?.to_vec();
+        Self::utf8_string(offset, buf)
     }
 
-    fn read_date_time(&mut self) -> Result<DateTime<Utc>, Box<dyn Error>> {
-        // 0x15
-        self.seek_from_current(1)?;
+    fn read_guid(&mut self) -> Result<Uuid, BWSaveGameError> {
+        let guid = Uuid::from_bytes_le(self.read_array()?);
+        Ok(guid)
+    }
 
-        let date_time_string = self.read_null_terminated_string()?;
+    pub(crate) fn seek_from_start(&mut self, n: u64) {
+        self.c.set_position(n);
+    }
 
-        let timestamp = DateTime::parse_from_rfc3339(&date_time_string)?
+    fn read_date_time(&mut self) -> Result<DateTime<Utc>, BWSaveGameError> {
+        let offset = self.c.position();
+        let date_time_string = self.read_prefixed_string()?;
+
+        let timestamp = DateTime::parse_from_rfc3339(&date_time_string)
+            .map_err(|e| Self::bad_value(offset, format!("time {:?} isn't RFC 3339: {}", date_time_string, e)))?
             .with_timezone(&Utc);
 
         Ok(timestamp)
     }
 
-    pub(crate) fn read_u16_le(&mut self) -> Result<u16, Box<dyn Error>> {
-        let buf = self.read_bytes(2)?;
-        let arr: [u8; 2] = buf.try_into()?;
-        Ok(u16::from_le_bytes(arr))
+    pub(crate) fn read_u16_le(&mut self) -> Result<u16, BWSaveGameError> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    pub(crate) fn read_i32_le(&mut self) -> Result<i32, BWSaveGameError> {
+        Ok(i32::from_le_bytes(self.read_array()?))
+    }
+
+    pub(crate) fn read_u32_le(&mut self) -> Result<u32, BWSaveGameError> {
+        Ok(u32::from_le_bytes(self.read_array()?))
     }
 
-    pub(crate) fn read_i32_le(&mut self) -> Result<i32, Box<dyn Error>> {
-        let buf = self.read_bytes(4)?;
-        let arr: [u8; 4] = buf.try_into()?;
-        Ok(i32::from_le_bytes(arr))
+    pub(crate) fn read_i64_le(&mut self) -> Result<i64, BWSaveGameError> {
+        Ok(i64::from_le_bytes(self.read_array()?))
     }
 
-    pub(crate) fn read_u32_le(&mut self) -> Result<u32, Box<dyn Error>> {
-        let buf = self.read_bytes(4)?;
-        let arr: [u8; 4] = buf.try_into()?;
-        Ok(u32::from_le_bytes(arr))
+    pub(crate) fn read_u8(&mut self) -> Result<u8, BWSaveGameError> {
+        let [byte] = self.read_array()?;
+        Ok(byte)
     }
 
-    pub(crate) fn read_i64_le(&mut self) -> Result<i64, Box<dyn Error>> {
-        let buf = self.read_bytes(8)?;
-        let arr: [u8; 8] = buf.try_into()?;
+    pub(crate) fn read_null_terminated_string(&mut self) -> Result<String, BWSaveGameError> {
+        let offset = self.c.position() as usize;
+        let data = self.c.get_ref();
+
+        let rest = data.get(offset..).unwrap_or_default();
+        let len = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+        let buf = rest[..len].to_vec();
+
+        // Skips the terminator too if there is one.
+        self.seek_from_start((offset + len + 1).min(data.len()) as u64);
 
-        Ok(i64::from_le_bytes(arr))
+        Self::utf8_string(offset as u64, buf)
     }
 
-    pub(crate) fn read_u8(&mut self) -> io::Result<u8> {
-        let mut buf = [0; 1];
-        self.c.read_exact(&mut buf)?;
-        Ok(buf[0])
+    // Length prefix includes the null terminator.
+    fn read_prefixed_string(&mut self) -> Result<String, BWSaveGameError> {
+        let len = self.read_varint()? as usize;
+        let offset = self.c.position();
+        let buf = self.read_bytes(len)?;
+        let buf = buf.strip_suffix(&[0]).unwrap_or(buf).to_vec();
+
+        Self::utf8_string(offset, buf)
     }
 
-    pub(crate) fn read_null_terminated_string(&mut self) -> Result<String, Box<dyn Error>> {
-        let mut buffer: Vec<u8> = Vec::new();
-        let mut byte = [0u8; 1];
+    fn read_varint(&mut self) -> Result<u32, BWSaveGameError> {
+        let pos = self.c.position() as usize;
+        let (value, len) = varint::decode(self.c.get_ref().get(pos..).unwrap_or_default())
+            .ok_or_else(|| Self::bad_value(pos as u64, "varint is cut off or too long".to_string()))?;
 
-        loop {
-            match self.c.read(&mut byte) {
-                Ok(0) => break,
-                Ok(_) if byte[0] == 0 => break,
-                Ok(_) => buffer.push(byte[0]),
-                Err(e) => return Err(e.into()),
-            }
+        self.c.set_position((pos + len) as u64);
+        Ok(value)
+    }
+
+    // Widths the typed parser has always used for these keys.
+    fn known_value_len(name: &str) -> Option<ValueLen> {
+        let value_len = match name {
+            "checkpointid" | "faction" | "lineage" | "archetype" | "questid" | "activecareer"
+            | "keybindingprofile" | "level" | "difficulty" | "tone" | "voice" | "pronoun"
+            | "gender" | "cdur" | "playtime" | "buildcl" | "savefileversion" | "project"
+            | "version" => ValueLen::Fixed(4),
+            "requestid" | "unixtimeseconds" => ValueLen::Fixed(8),
+            "projdata" | "nexussessionid" => ValueLen::Fixed(2),
+            "poststreaminginstall" | "afterpointofnoreturn" | "licenseeversion" => ValueLen::Fixed(1),
+            "expansion" => ValueLen::Fixed(9),
+            "uid" => ValueLen::Fixed(16),
+            "charname" | "transitionpointname" | "type" | "description" | "sessionid"
+            | "time" => ValueLen::Prefixed,
+            _ => return None,
+        };
+
+        Some(value_len)
+    }
+
+    fn read_entry(&mut self) -> Result<MetadataEntry, BWSaveGameError> {
+        let offset = self.c.position();
+        let tag = self.read_u8()?;
+
+        // End of a nested object, nothing follows.
+        if tag == 0x00 {
+            return Ok(MetadataEntry {
+                offset,
+                tag,
+                name: String::new(),
+                value: Vec::new(),
+            });
         }
 
-        let s = String::from_utf8(buffer)?;
-        Ok(s)
+        let name = if tag & 0x80 == 0 {
+            self.read_null_terminated_string()?
+        } else {
+            String::new()
+        };
+
+        let value_offset = self.c.position();
+
+        let value_len = Self::known_value_len(&name)
+            .or_else(|| ValueLen::from_tag(tag))
+            .ok_or(BWSaveGameError::UnknownMetadataKey {
+                block: ErrorBlock::BlockOne,
+                offset,
+                name: name.clone(),
+            })?;
+
+        let len = match value_len {
+            ValueLen::Fixed(n) => n,
+            ValueLen::Prefixed => {
+                let n = self.read_varint()? as usize;
+                (self.c.position() - value_offset) as usize + n
+            }
+        };
+
+        self.seek_from_start(value_offset);
+        let value = self.read_bytes(len)?.to_vec();
+
+        let entry = MetadataEntry {
+            offset,
+            tag,
+            name,
+            value,
+        };
+
+        Ok(entry)
     }
 
-    fn read_name_string(&mut self) -> Result<String, Box<dyn Error>>  {
-        // Prefix byte.
-        self.seek_from_current(1)?;
-        let name = self.read_null_terminated_string()?;
-        Ok(name)
+    // Splits the block into its object prefix and an ordered list of raw entries.
+    // Nothing is dropped, so writing the prefix and entries back gives the same block.
+    pub fn parse_entries(&mut self) -> Result<(), BWSaveGameError> {
+        let eof_offset = self.c.get_ref().len() as u64;
+
+        // Object marker and its size.
+        self.seek_from_start(0);
+        self.read_u8()?;
+        self.read_varint()?;
+
+        let prefix_len = self.c.position() as usize;
+        self.prefix = self.c.get_ref()[..prefix_len].to_vec();
+
+        let mut entries = Vec::new();
+        while self.c.position() < eof_offset {
+            entries.push(self.read_entry()?);
+        }
+
+        self.entries = entries;
+
+        Ok(())
     }
 
-    fn read_bool(&mut self) -> Result<bool, Box<dyn Error>> {
+    fn read_bool(&mut self) -> Result<bool, BWSaveGameError> {
+        let offset = self.c.position();
         let value = self.read_u8()?;
         let value_bool = match value {
             0x0 => false,
             0x1 => true,
-            _ => return Err(format!("Invalid bool value: {:#X}", value).into()),
+            _ => return Err(BWSaveGameError::UnknownEnumValue {
+                block: ErrorBlock::BlockOne,
+                offset,
+                enum_name: "bool",
+                value: format!("{:#X}", value),
+            }),
         };
 
         Ok(value_bool)
     }
 
-    fn get_stream_len(&mut self) -> Result<u64, Box<dyn Error>> {
-        let original_pos = self.c.stream_position()?;
-        let len = self.c.seek(SeekFrom::End(0))?;
-        self.seek_from_start(original_pos)?;
-        Ok(len)
-    }
+    // The order can vary, so the typed view is filled in from whatever entries are present.
+    // Keys that aren't known are left in the entry list untouched.
+    pub fn parse_metadata(&mut self) -> Result<(), BWSaveGameError> {
+        self.parse_entries()?;
 
-    // The order can vary so we need to parse in a loop. The prefix bytes don't have anything to do with types.
-    pub(crate) fn parse_metadata(&mut self) -> Result<(), Box<dyn Error>> {
         let mut meta = BWSaveGameMetadata::default();
-        let eof_offset = self.get_stream_len()?;
-
-        self.seek_from_start(3)?;
-
-        loop {
-            let pos = self.c.stream_position()?;
+        let entries = self.entries.clone();
 
-            if pos >= eof_offset-1 {
-                // println!("Reached EOF. pos: {}, EOF: {} ", pos, eof_offset);
-                break
+        for entry in &entries {
+            if entry.tag == 0x00 {
+                continue;
             }
 
-            let name = self.read_name_string()?;
-            match name.as_str() {
+            self.seek_from_start(entry.value_offset());
+
+            match entry.name.as_str() {
                 "checkpointid" => {
                     meta.checkpoint_id = self.read_u32_le()?;
                 }
 
                 "faction" => {
-                    let v = self.read_u32_le()?;
-                    let faction = CharacterFaction::from_u32(v)
-                        .ok_or(format!("Unknown CharacterFaction: {}", v))?;
-                    meta.faction = faction;
+                    meta.faction = CharacterFaction::from_u32(self.read_u32_le()?);
                 }
                 "lineage" => {
-                    let v = self.read_u32_le()?;
-                    let lineage = CharacterLineage::from_u32(v)
-                        .ok_or(format!("Unknown CharacterLineage: {}", v))?;
-                    meta.lineage = lineage;
+                    meta.lineage = CharacterLineage::from_u32(self.read_u32_le()?);
 
                 }
                 "archetype" => {
-                    let v = self.read_u32_le()?;
-                    let arche_type = CharacterArchetype::from_u32(v)
-                        .ok_or(format!("Unknown CharacterArchetype: {}", v))?;
-                    meta.arche_type = arche_type;
+                    meta.arche_type = CharacterArchetype::from_u32(self.read_u32_le()?);
 
                 }
                 "charname" => {
-                    // 0x07 prefix
-                    self.seek_from_current(1)?;
-                    meta.character_name = self.read_null_terminated_string()?;
+                    meta.character_name = self.read_prefixed_string()?;
                 }
                 "questid" => {
                     meta.quest_id = self.read_u32_le()?;
@@ -182,52 +275,30 @@ impl BWSaveGameMetadataReader {
                     meta.request_id = self.read_i64_le()?;
                 }
                 "keybindingprofile" => {
-                    let v = self.read_u32_le()?;
-                    let key_binding_profile = KeyBindingProfile::from_u32(v)
-                        .ok_or(format!("Unknown KeyBindingProfile: {}", v))?;
-                    meta.key_binding_profile = key_binding_profile;
-                    self.seek_from_current(1)?;
+                    meta.key_binding_profile = KeyBindingProfile::from_u32(self.read_u32_le()?);
                 }
                 "level" => {
                     meta.character_level = self.read_u32_le()?;
                 }
                 "difficulty" => {
-                    let v = self.read_u32_le()?;
-
-                    let difficulty = Difficulty::from_u32(v)
-                        .ok_or(format!("Unknown Difficulty: {}", v))?;
-                    meta.difficulty = difficulty;
+                    meta.difficulty = Difficulty::from_u32(self.read_u32_le()?);
                 }
                 "tone" => {
-                    let v = self.read_u32_le()?;
-                    let voice_tone = CharacterVoiceTone::from_u32(v)
-                        .ok_or(format!("Unknown CharacterVoiceTone: {}", v))?;
-                    meta.voice_tone = voice_tone;
+                    meta.voice_tone = CharacterVoiceTone::from_u32(self.read_u32_le()?);
                 }
                 "voice" => {
-                    let v = self.read_u32_le()?;
-                    let voice = CharacterVoice::from_u32(v)
-                        .ok_or(format!("Unknown CharacterVoice: {}", v))?;
-                    meta.voice = voice;
+                    meta.voice = CharacterVoice::from_u32(self.read_u32_le()?);
                 }
                 "pronoun" => {
-                    let v = self.read_u32_le()?;
-                    let pronouns = CharacterPronouns::from_u32(v)
-                        .ok_or(format!("Unknown CharacterPronouns: {}", v))?;
-                    meta.pronouns = pronouns;
+                    meta.pronouns = CharacterPronouns::from_u32(self.read_u32_le()?);
 
                 }
                 "gender" => {
-                    let v = self.read_u32_le()?;
-                    let gender = CharacterGender::from_u32(v)
-                        .ok_or(format!("Unknown CharacterGender: {}", v))?;
-                    meta.gender = gender;
+                    meta.gender = CharacterGender::from_u32(self.read_u32_le()?);
 
                 }
                 "transitionpointname" => {
-                    // Length prefix.
-                    self.seek_from_current(1)?;
-                    meta.transition_point_name = self.read_null_terminated_string()?;
+                    meta.transition_point_name = self.read_prefixed_string()?;
                 }
                 "projdata" => {
                     meta.project_data = self.read_u16_le()?;
@@ -237,12 +308,6 @@ impl BWSaveGameMetadataReader {
                 }
                 "afterpointofnoreturn" => {
                     meta.after_point_of_no_return = self.read_bool()?;
-                    let b = self.read_u8()?;
-
-                    // Key binding profile present.
-                    if b != 0 {
-                        self.seek_from_current(-1)?;
-                    }
                 }
                 "cdur" => {
                     meta.cdur = self.read_u32_le()?;
@@ -251,25 +316,16 @@ impl BWSaveGameMetadataReader {
                     meta.playtime = self.read_u32_le()?;
                 }
                 "type" => {
-                    // 0x17 prefix
-                    self.seek_from_current(1)?;
-                    let v = self.read_null_terminated_string()?;
-                    let save_type = SaveType::from_str(&v)
-                        .ok_or(format!("Unknown SaveType: {}", v))?;
-                    meta.save_type = save_type;
+                    meta.save_type = SaveType::from_str(&self.read_prefixed_string()?);
                 }
                 "description" => {
-                    // Length prefix.
-                    self.seek_from_current(1)?;
-                    meta.description = self.read_null_terminated_string()?
+                    meta.description = self.read_prefixed_string()?
                 }
                 "nexussessionid" => {
                     meta.nexus_session_id = self.read_u16_le()?;
                 }
                 "sessionid" => {
-                    // Length prefix, 0x19.
-                    self.seek_from_current(1)?;
-                    meta.session_id = self.read_null_terminated_string()?;
+                    meta.session_id = self.read_prefixed_string()?;
                 }
                 "buildcl" => {
                     meta.buildcl = self.read_u32_le()?
@@ -281,7 +337,7 @@ impl BWSaveGameMetadataReader {
                     meta.date_time = self.read_date_time()?;
                 }
                 "expansion" => {
-                    meta.expansion = self.read_bytes(9)?.try_into()?;
+                    meta.expansion = self.read_array()?;
                 }
                 "savefileversion" => {
                     meta.save_file_version = self.read_i32_le()?
@@ -304,9 +360,7 @@ impl BWSaveGameMetadataReader {
                 "uid" => {
                     meta.guid = self.read_guid()?;
                 }
-                _ => {
-                    return Err(format!("Unknown name: {}", name).into());
-                }
+                _ => {}
             }
 
         }
@@ -318,3 +372,61 @@ impl BWSaveGameMetadataReader {
     }
 
 }
+
+impl MetadataEntry {
+    pub fn value_offset(&self) -> u64 {
+        if self.tag == 0x00 {
+            return self.offset + 1;
+        }
+
+        let name_len = if self.tag & 0x80 == 0 { self.name.len() as u64 + 1 } else { 0 };
+        self.offset + 1 + name_len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_data::{block_one, field, metadata_entries, string_value};
+    use super::*;
+
+    fn sample_block_one() -> Vec<u8> {
+        block_one(&metadata_entries(), 0, 1)
+    }
+
+    #[test]
+    fn parses_entries_and_typed_fields() {
+        let data = sample_block_one();
+        let mut r = BWSaveGameMetadataReader::new(data.clone());
+        r.parse_metadata().unwrap();
+
+        assert_eq!(r.prefix, &data[..2]);
+        assert_eq!(r.entries.len(), 4);
+        assert_eq!(r.entries[3].name, "newpatchkey");
+        assert_eq!(r.entries[3].value, 7u32.to_le_bytes());
+        assert_eq!(r.metadata.character_level, 6);
+        assert_eq!(r.metadata.description, "Save 10");
+    }
+
+    #[test]
+    fn truncated_entry_is_an_io_error_with_its_offset() {
+        let mut data = sample_block_one();
+        data.truncate(data.len() - 2);
+
+        let mut r = BWSaveGameMetadataReader::new(data.clone());
+        let err = r.parse_entries().unwrap_err();
+
+        let value_offset = data.len() as u64 - 2;
+        assert!(matches!(err, BWSaveGameError::Io { block: ErrorBlock::BlockOne, offset, .. } if offset == value_offset));
+    }
+
+    #[test]
+    fn bad_utf8_is_a_bad_value() {
+        let mut value = string_value("Save 10");
+        value[1] = 0xFF;
+        let data = block_one(&[field(0x07, "description", &value)], 0, 1);
+
+        let mut r = BWSaveGameMetadataReader::new(data);
+        let err = r.parse_metadata().unwrap_err();
+        assert!(matches!(err, BWSaveGameError::BadValue { block: ErrorBlock::BlockOne, .. }));
+    }
+}
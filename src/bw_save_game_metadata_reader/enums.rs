@@ -1,6 +1,34 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Default, Serialize)]
+// How many bytes follow an entry's name.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueLen {
+    Fixed(usize),
+    // Varint length, then that many bytes.
+    Prefixed,
+}
+
+impl ValueLen {
+    // Only used for keys the parser doesn't know. The low 5 bits are the value type,
+    // the high bit means the entry has no name.
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        match tag & 0x1F {
+            0x01 | 0x02 => Some(Self::Prefixed),
+            0x06 => Some(Self::Fixed(1)),
+            0x07 => Some(Self::Prefixed),
+            0x08 => Some(Self::Fixed(4)),
+            0x09 => Some(Self::Fixed(8)),
+            0x0B => Some(Self::Fixed(4)),
+            0x0C => Some(Self::Fixed(8)),
+            0x0F => Some(Self::Fixed(16)),
+            0x10 => Some(Self::Fixed(20)),
+            0x13 => Some(Self::Prefixed),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub enum SaveType {
     #[default]
     Manual,
@@ -13,27 +41,61 @@ pub enum SaveType {
     CharGen,
     Count,
     Invalid,
+    Unknown(String),
 }
 
 impl SaveType {
-    pub fn from_str(s: &str) -> Option<Self> {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Self {
+        match s {
+            "BWSavegameType_Manual" => Self::Manual,
+            "BWSavegameType_Auto" => Self::Auto,
+            "BWSavegameType_Safety" => Self::Safety,
+            "BWSavegameType_Chapter" => Self::Chapter,
+            "BWSavegameType_Milestone" => Self::Milestone,
+            "BWSavegameType_Decision" => Self::Decision,
+            "BWSavegameType_PointOfNoReturn" => Self::PointOfNoReturn,
+            "BWSavegameType_CharGen" => Self::CharGen,
+            "BWSavegameType_Count" => Self::Count,
+            "BWSavegameType_Invalid" => Self::Invalid,
+            _ => Self::Unknown(s.to_string()),
+        }
+    }
+
+    pub fn to_str(&self) -> &str {
+        match self {
+            Self::Manual => "BWSavegameType_Manual",
+            Self::Auto => "BWSavegameType_Auto",
+            Self::Safety => "BWSavegameType_Safety",
+            Self::Chapter => "BWSavegameType_Chapter",
+            Self::Milestone => "BWSavegameType_Milestone",
+            Self::Decision => "BWSavegameType_Decision",
+            Self::PointOfNoReturn => "BWSavegameType_PointOfNoReturn",
+            Self::CharGen => "BWSavegameType_CharGen",
+            Self::Count => "BWSavegameType_Count",
+            Self::Invalid => "BWSavegameType_Invalid",
+            Self::Unknown(s) => s,
+        }
+    }
+
+    pub fn from_name(s: &str) -> Option<Self> {
         match s {
-            "BWSavegameType_Manual" => Some(Self::Manual),
-            "BWSavegameType_Auto" => Some(Self::Auto),
-            "BWSavegameType_Safety" => Some(Self::Safety),
-            "BWSavegameType_Chapter" => Some(Self::Chapter),
-            "BWSavegameType_Milestone" => Some(Self::Milestone),
-            "BWSavegameType_Decision" => Some(Self::Decision),
-            "BWSavegameType_PointOfNoReturn" => Some(Self::PointOfNoReturn),
-            "BWSavegameType_CharGen" => Some(Self::CharGen),
-            "BWSavegameType_Count" => Some(Self::Count),
-            "BWSavegameType_Invalid" => Some(Self::Invalid),
+            "Manual" => Some(Self::Manual),
+            "Auto" => Some(Self::Auto),
+            "Safety" => Some(Self::Safety),
+            "Chapter" => Some(Self::Chapter),
+            "Milestone" => Some(Self::Milestone),
+            "Decision" => Some(Self::Decision),
+            "PointOfNoReturn" => Some(Self::PointOfNoReturn),
+            "CharGen" => Some(Self::CharGen),
+            "Count" => Some(Self::Count),
+            "Invalid" => Some(Self::Invalid),
             _ => None,
         }
     }
 }
 
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub enum CharacterArchetype {
     #[default]
     Crow,
@@ -68,70 +130,168 @@ pub enum CharacterArchetype {
     WardenTechnique,
     Warrior,
     Watcher,
+    Unknown(u32),
 }
 
 impl CharacterArchetype {
-    pub fn from_u32(n: u32) -> Option<Self> {
+    pub fn from_u32(n: u32) -> Self {
         match n {
-            116806840 => Some(Self::FollowerBellara),
-            1326121707 => Some(Self::FollowerHarding),
-            1480587723 => Some(Self::Ranger02),
-            1486725849 => Some(Self::WardenTechnique),
-            1837455073 => Some(Self::ShadowEvoker),
-            1887180846 => Some(Self::FollowerSpite),
-            1902731980 => Some(Self::Rogue),
-            1928218134 => Some(Self::FollowerNeve),
-            2143795149 => Some(Self::FollowerLucanis),
-            2257715964 => Some(Self::WardenStrategy),
-            2325381541 => Some(Self::Watcher),
-            2366407241 => Some(Self::Crow),
-            240491018 => Some(Self::Mage),
-            2602884150 => Some(Self::FollowerDavrin),
-            267923513 => Some(Self::Warrior),
-            2714609019 => Some(Self::Desperado),
-            28757921 => Some(Self::WardenPower),
-            2903517207 => Some(Self::Warden4),
-            291152393 => Some(Self::Dalish),
-            2930410500 => Some(Self::PlayerRGZtest),
-            294481 => Some(Self::WardenCine),
-            3417468734 => Some(Self::FollowerVarric),
-            3509394015 => Some(Self::NullPlayer),
-            3517341798 => Some(Self::Ranger01),
-            3723887171 => Some(Self::WardenArt),
-            3734548853 => Some(Self::FollowerEmmrich),
-            3822852109 => Some(Self::Ranger03),
-            394763556 => Some(Self::FollowerSolas),
-            3998641339 => Some(Self::WardenChallenger),
-            4003900063 => Some(Self::WardenEndurance),
-            4131396826 => Some(Self::FollowerTaash),
-            624386075 => Some(Self::Fortune),
+            116806840 => Self::FollowerBellara,
+            1326121707 => Self::FollowerHarding,
+            1480587723 => Self::Ranger02,
+            1486725849 => Self::WardenTechnique,
+            1837455073 => Self::ShadowEvoker,
+            1887180846 => Self::FollowerSpite,
+            1902731980 => Self::Rogue,
+            1928218134 => Self::FollowerNeve,
+            2143795149 => Self::FollowerLucanis,
+            2257715964 => Self::WardenStrategy,
+            2325381541 => Self::Watcher,
+            2366407241 => Self::Crow,
+            240491018 => Self::Mage,
+            2602884150 => Self::FollowerDavrin,
+            267923513 => Self::Warrior,
+            2714609019 => Self::Desperado,
+            28757921 => Self::WardenPower,
+            2903517207 => Self::Warden4,
+            291152393 => Self::Dalish,
+            2930410500 => Self::PlayerRGZtest,
+            294481 => Self::WardenCine,
+            3417468734 => Self::FollowerVarric,
+            3509394015 => Self::NullPlayer,
+            3517341798 => Self::Ranger01,
+            3723887171 => Self::WardenArt,
+            3734548853 => Self::FollowerEmmrich,
+            3822852109 => Self::Ranger03,
+            394763556 => Self::FollowerSolas,
+            3998641339 => Self::WardenChallenger,
+            4003900063 => Self::WardenEndurance,
+            4131396826 => Self::FollowerTaash,
+            624386075 => Self::Fortune,
+            _ => Self::Unknown(n),
+        }
+    }
+
+    pub fn to_u32(&self) -> u32 {
+        match self {
+            Self::FollowerBellara => 116806840,
+            Self::FollowerHarding => 1326121707,
+            Self::Ranger02 => 1480587723,
+            Self::WardenTechnique => 1486725849,
+            Self::ShadowEvoker => 1837455073,
+            Self::FollowerSpite => 1887180846,
+            Self::Rogue => 1902731980,
+            Self::FollowerNeve => 1928218134,
+            Self::FollowerLucanis => 2143795149,
+            Self::WardenStrategy => 2257715964,
+            Self::Watcher => 2325381541,
+            Self::Crow => 2366407241,
+            Self::Mage => 240491018,
+            Self::FollowerDavrin => 2602884150,
+            Self::Warrior => 267923513,
+            Self::Desperado => 2714609019,
+            Self::WardenPower => 28757921,
+            Self::Warden4 => 2903517207,
+            Self::Dalish => 291152393,
+            Self::PlayerRGZtest => 2930410500,
+            Self::WardenCine => 294481,
+            Self::FollowerVarric => 3417468734,
+            Self::NullPlayer => 3509394015,
+            Self::Ranger01 => 3517341798,
+            Self::WardenArt => 3723887171,
+            Self::FollowerEmmrich => 3734548853,
+            Self::Ranger03 => 3822852109,
+            Self::FollowerSolas => 394763556,
+            Self::WardenChallenger => 3998641339,
+            Self::WardenEndurance => 4003900063,
+            Self::FollowerTaash => 4131396826,
+            Self::Fortune => 624386075,
+            Self::Unknown(n) => *n,
+        }
+    }
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "Crow" => Some(Self::Crow),
+            "Dalish" => Some(Self::Dalish),
+            "Desperado" => Some(Self::Desperado),
+            "FollowerBellara" => Some(Self::FollowerBellara),
+            "FollowerDavrin" => Some(Self::FollowerDavrin),
+            "FollowerEmmrich" => Some(Self::FollowerEmmrich),
+            "FollowerHarding" => Some(Self::FollowerHarding),
+            "FollowerLucanis" => Some(Self::FollowerLucanis),
+            "FollowerNeve" => Some(Self::FollowerNeve),
+            "FollowerSolas" => Some(Self::FollowerSolas),
+            "FollowerSpite" => Some(Self::FollowerSpite),
+            "FollowerTaash" => Some(Self::FollowerTaash),
+            "FollowerVarric" => Some(Self::FollowerVarric),
+            "Fortune" => Some(Self::Fortune),
+            "Mage" => Some(Self::Mage),
+            "NullPlayer" => Some(Self::NullPlayer),
+            "PlayerRGZtest" => Some(Self::PlayerRGZtest),
+            "Ranger01" => Some(Self::Ranger01),
+            "Ranger02" => Some(Self::Ranger02),
+            "Ranger03" => Some(Self::Ranger03),
+            "Rogue" => Some(Self::Rogue),
+            "ShadowEvoker" => Some(Self::ShadowEvoker),
+            "Warden4" => Some(Self::Warden4),
+            "WardenArt" => Some(Self::WardenArt),
+            "WardenChallenger" => Some(Self::WardenChallenger),
+            "WardenCine" => Some(Self::WardenCine),
+            "WardenEndurance" => Some(Self::WardenEndurance),
+            "WardenPower" => Some(Self::WardenPower),
+            "WardenStrategy" => Some(Self::WardenStrategy),
+            "WardenTechnique" => Some(Self::WardenTechnique),
+            "Warrior" => Some(Self::Warrior),
+            "Watcher" => Some(Self::Watcher),
             _ => None,
         }
     }
 }
 
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub enum CharacterLineage {
     #[default]
     Elf,
     Dwarf,
     Human,
     Qunari,
+    Unknown(u32),
 }
 
 impl CharacterLineage {
-    pub fn from_u32(n: u32) -> Option<Self> {
+    pub fn from_u32(n: u32) -> Self {
         match n {
-            0 => Some(Self::Human),
-            1 => Some(Self::Dwarf),
-            2 => Some(Self::Elf),
-            3 => Some(Self::Qunari),
+            0 => Self::Human,
+            1 => Self::Dwarf,
+            2 => Self::Elf,
+            3 => Self::Qunari,
+            _ => Self::Unknown(n),
+        }
+    }
+
+    pub fn to_u32(&self) -> u32 {
+        match self {
+            Self::Human => 0,
+            Self::Dwarf => 1,
+            Self::Elf => 2,
+            Self::Qunari => 3,
+            Self::Unknown(n) => *n,
+        }
+    }
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "Dwarf" => Some(Self::Dwarf),
+            "Elf" => Some(Self::Elf),
+            "Human" => Some(Self::Human),
+            "Qunari" => Some(Self::Qunari),
             _ => None,
         }
     }
 }
 
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub enum CharacterFaction {
     #[default]
     AntivanCrows,
@@ -140,92 +300,191 @@ pub enum CharacterFaction {
     ShadowDragons,
     TheMournWatch,
     VeilJumpers,
+    Unknown(u32),
 }
 
 impl CharacterFaction {
-    pub fn from_u32(n: u32) -> Option<Self> {
+    pub fn from_u32(n: u32) -> Self {
         match n {
-            0 => Some(Self::GreyWardens),
-            1 => Some(Self::VeilJumpers),
-            2 => Some(Self::ShadowDragons),
-            3 => Some(Self::LordsOfFortune),
-            4 => Some(Self::TheMournWatch),
-            5 => Some(Self::AntivanCrows),
+            0 => Self::GreyWardens,
+            1 => Self::VeilJumpers,
+            2 => Self::ShadowDragons,
+            3 => Self::LordsOfFortune,
+            4 => Self::TheMournWatch,
+            5 => Self::AntivanCrows,
+            _ => Self::Unknown(n),
+        }
+    }
+
+    pub fn to_u32(&self) -> u32 {
+        match self {
+            Self::GreyWardens => 0,
+            Self::VeilJumpers => 1,
+            Self::ShadowDragons => 2,
+            Self::LordsOfFortune => 3,
+            Self::TheMournWatch => 4,
+            Self::AntivanCrows => 5,
+            Self::Unknown(n) => *n,
+        }
+    }
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "AntivanCrows" => Some(Self::AntivanCrows),
+            "GreyWardens" => Some(Self::GreyWardens),
+            "LordsOfFortune" => Some(Self::LordsOfFortune),
+            "ShadowDragons" => Some(Self::ShadowDragons),
+            "TheMournWatch" => Some(Self::TheMournWatch),
+            "VeilJumpers" => Some(Self::VeilJumpers),
             _ => None,
         }
     }
 }
 
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub enum CharacterGender {
     #[default]
     Male,
     Female,
+    Unknown(u32),
 }
 
 impl CharacterGender {
-    pub fn from_u32(n: u32) -> Option<Self> {
+    pub fn from_u32(n: u32) -> Self {
         match n {
-            0 => Some(Self::Male),
-            1 => Some(Self::Female),
+            0 => Self::Male,
+            1 => Self::Female,
+            _ => Self::Unknown(n),
+        }
+    }
+
+    pub fn to_u32(&self) -> u32 {
+        match self {
+            Self::Male => 0,
+            Self::Female => 1,
+            Self::Unknown(n) => *n,
+        }
+    }
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "Female" => Some(Self::Female),
+            "Male" => Some(Self::Male),
             _ => None,
         }
     }
 }
 
 
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub enum CharacterPronouns {
     #[default]
     HeHim,
     SheHer,
     TheyThem,
+    Unknown(u32),
 }
 
 impl CharacterPronouns {
-    pub fn from_u32(n: u32) -> Option<Self> {
+    pub fn from_u32(n: u32) -> Self {
         match n {
-            0 => Some(Self::HeHim),
-            1 => Some(Self::SheHer),
-            2 => Some(Self::TheyThem),
+            0 => Self::HeHim,
+            1 => Self::SheHer,
+            2 => Self::TheyThem,
+            _ => Self::Unknown(n),
+        }
+    }
+
+    pub fn to_u32(&self) -> u32 {
+        match self {
+            Self::HeHim => 0,
+            Self::SheHer => 1,
+            Self::TheyThem => 2,
+            Self::Unknown(n) => *n,
+        }
+    }
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "HeHim" => Some(Self::HeHim),
+            "SheHer" => Some(Self::SheHer),
+            "TheyThem" => Some(Self::TheyThem),
             _ => None,
         }
     }
 }
 
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub enum CharacterVoice {
     #[default]
     FeminineOne,
     FeminineTwo,
     MasculineOne,
     MasculineTwo,
+    Unknown(u32),
 }
 
 impl CharacterVoice {
-    pub fn from_u32(n: u32) -> Option<Self> {
+    pub fn from_u32(n: u32) -> Self {
         match n {
-            0 => Some(Self::FeminineTwo),
-            1 => Some(Self::MasculineTwo),
-            2 => Some(Self::FeminineOne),
-            3 => Some(Self::MasculineOne),
+            0 => Self::FeminineTwo,
+            1 => Self::MasculineTwo,
+            2 => Self::FeminineOne,
+            3 => Self::MasculineOne,
+            _ => Self::Unknown(n),
+        }
+    }
+
+    pub fn to_u32(&self) -> u32 {
+        match self {
+            Self::FeminineTwo => 0,
+            Self::MasculineTwo => 1,
+            Self::FeminineOne => 2,
+            Self::MasculineOne => 3,
+            Self::Unknown(n) => *n,
+        }
+    }
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "FeminineOne" => Some(Self::FeminineOne),
+            "FeminineTwo" => Some(Self::FeminineTwo),
+            "MasculineOne" => Some(Self::MasculineOne),
+            "MasculineTwo" => Some(Self::MasculineTwo),
             _ => None,
         }
     }
 }
 
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub enum CharacterVoiceTone {
     #[default]
     Low,
     Medium,
+    Unknown(u32),
 }
 
 impl CharacterVoiceTone {
-    pub fn from_u32(n: u32) -> Option<Self> {
+    pub fn from_u32(n: u32) -> Self {
         match n {
-            0 => Some(Self::Medium),
-            1 => Some(Self::Low),
+            0 => Self::Medium,
+            1 => Self::Low,
+            _ => Self::Unknown(n),
+        }
+    }
+
+    pub fn to_u32(&self) -> u32 {
+        match self {
+            Self::Medium => 0,
+            Self::Low => 1,
+            Self::Unknown(n) => *n,
+        }
+    }
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "Low" => Some(Self::Low),
+            "Medium" => Some(Self::Medium),
             _ => None,
         }
     }
@@ -243,7 +502,7 @@ impl CharacterVoiceTone {
 // nightmare 5
 // custom 6
 
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Difficulty {
     #[default]
     Adventurer,
@@ -252,36 +511,80 @@ pub enum Difficulty {
     Nightmare,
     Storyteller,
     Underdog,
+    Unknown(u32),
 }
 
 impl Difficulty {
-    pub fn from_u32(n: u32) -> Option<Self> {
+    pub fn from_u32(n: u32) -> Self {
         match n {
-            1 => Some(Self::Adventurer),
-            2 => Some(Self::Storyteller),
-            3 => Some(Self::Keeper),
-            4 => Some(Self::Underdog),
-            5 => Some(Self::Nightmare),
-            6 => Some(Self::Custom),
+            1 => Self::Adventurer,
+            2 => Self::Storyteller,
+            3 => Self::Keeper,
+            4 => Self::Underdog,
+            5 => Self::Nightmare,
+            6 => Self::Custom,
+            _ => Self::Unknown(n),
+        }
+    }
+
+    pub fn to_u32(&self) -> u32 {
+        match self {
+            Self::Adventurer => 1,
+            Self::Storyteller => 2,
+            Self::Keeper => 3,
+            Self::Underdog => 4,
+            Self::Nightmare => 5,
+            Self::Custom => 6,
+            Self::Unknown(n) => *n,
+        }
+    }
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "Adventurer" => Some(Self::Adventurer),
+            "Custom" => Some(Self::Custom),
+            "Keeper" => Some(Self::Keeper),
+            "Nightmare" => Some(Self::Nightmare),
+            "Storyteller" => Some(Self::Storyteller),
+            "Underdog" => Some(Self::Underdog),
             _ => None,
         }
     }
 }
 
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub enum KeyBindingProfile {
     #[default]
     Mage,
     Rogue,
-    Warrior
+    Warrior,
+    Unknown(u32),
 }
 
 impl KeyBindingProfile {
-    pub fn from_u32(n: u32) -> Option<Self> {
+    pub fn from_u32(n: u32) -> Self {
         match n {
-            0 => Some(Self::Warrior),
-            1 => Some(Self::Rogue),
-            2 => Some(Self::Mage),
+            0 => Self::Warrior,
+            1 => Self::Rogue,
+            2 => Self::Mage,
+            _ => Self::Unknown(n),
+        }
+    }
+
+    pub fn to_u32(&self) -> u32 {
+        match self {
+            Self::Warrior => 0,
+            Self::Rogue => 1,
+            Self::Mage => 2,
+            Self::Unknown(n) => *n,
+        }
+    }
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "Mage" => Some(Self::Mage),
+            "Rogue" => Some(Self::Rogue),
+            "Warrior" => Some(Self::Warrior),
             _ => None,
         }
     }
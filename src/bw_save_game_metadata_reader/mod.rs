@@ -1,3 +1,3 @@
 pub mod bw_save_game_metadata_reader;
-pub(crate) mod enums;
-pub(crate) mod structs;
+pub mod enums;
+pub mod structs;
@@ -1,16 +1,27 @@
 use std::io::Cursor;
 use chrono::{DateTime, Utc};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use crate::bw_save_game_metadata_reader::enums::*;
 
 pub struct BWSaveGameMetadataReader {
     pub(crate) c: Cursor<Vec<u8>>,
-    pub(crate) buffer: Vec<u8>,
-    pub(crate) metadata: BWSaveGameMetadata,
+    pub metadata: BWSaveGameMetadata,
+    pub prefix: Vec<u8>,
+    pub entries: Vec<MetadataEntry>,
 }
 
-#[derive(Debug, Default, Serialize)]
+// A tag of 0x00 is the end of a nested object and has no name or value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataEntry {
+    pub offset: u64,
+    pub tag: u8,
+    pub name: String,
+    // Raw bytes as stored, including any length prefix.
+    pub value: Vec<u8>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct BWSaveGameMetadata {
     pub faction: CharacterFaction,
     pub lineage: CharacterLineage,
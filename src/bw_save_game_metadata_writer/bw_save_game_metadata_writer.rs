@@ -0,0 +1,287 @@
+use std::error::Error;
+use chrono::{DateTime, SecondsFormat, Utc};
+use uuid::Uuid;
+use crate::bw_save_game_metadata_reader::enums::*;
+use crate::bw_save_game_metadata_reader::structs::{BWSaveGameMetadata, MetadataEntry};
+use crate::bw_save_game_metadata_writer::structs::BWSaveGameMetadataWriter;
+use crate::varint;
+
+impl BWSaveGameMetadataWriter {
+    pub fn new(prefix: Vec<u8>, entries: Vec<MetadataEntry>) -> Self {
+        let orig_body_len = entries.iter()
+            .map(Self::entry_len)
+            .sum();
+
+        Self {
+            prefix,
+            orig_body_len,
+            entries,
+        }
+    }
+
+    fn entry_len(entry: &MetadataEntry) -> usize {
+        if entry.tag == 0x00 {
+            return 1;
+        }
+
+        let name_len = if entry.tag & 0x80 == 0 { entry.name.len() + 1 } else { 0 };
+        1 + name_len + entry.value.len()
+    }
+
+    fn encode_string(s: &str) -> Vec<u8> {
+        let mut bytes = varint::encode(s.len() as u32 + 1);
+        bytes.extend_from_slice(s.as_bytes());
+        bytes.push(0x00);
+        bytes
+    }
+
+    // "version" shows up twice, occurrence picks which of the two fields it's written from.
+    fn encode_value(meta: &BWSaveGameMetadata, name: &str, occurrence: usize) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let value = match name {
+            "checkpointid" => meta.checkpoint_id.to_le_bytes().to_vec(),
+            "faction" => meta.faction.to_u32().to_le_bytes().to_vec(),
+            "lineage" => meta.lineage.to_u32().to_le_bytes().to_vec(),
+            "archetype" => meta.arche_type.to_u32().to_le_bytes().to_vec(),
+            "charname" => Self::encode_string(&meta.character_name),
+            "questid" => meta.quest_id.to_le_bytes().to_vec(),
+            "activecareer" => meta.active_career.to_le_bytes().to_vec(),
+            "requestid" => meta.request_id.to_le_bytes().to_vec(),
+            "keybindingprofile" => meta.key_binding_profile.to_u32().to_le_bytes().to_vec(),
+            "level" => meta.character_level.to_le_bytes().to_vec(),
+            "difficulty" => meta.difficulty.to_u32().to_le_bytes().to_vec(),
+            "tone" => meta.voice_tone.to_u32().to_le_bytes().to_vec(),
+            "voice" => meta.voice.to_u32().to_le_bytes().to_vec(),
+            "pronoun" => meta.pronouns.to_u32().to_le_bytes().to_vec(),
+            "gender" => meta.gender.to_u32().to_le_bytes().to_vec(),
+            "transitionpointname" => Self::encode_string(&meta.transition_point_name),
+            "projdata" => meta.project_data.to_le_bytes().to_vec(),
+            "poststreaminginstall" => vec![meta.post_streaming_install as u8],
+            "afterpointofnoreturn" => vec![meta.after_point_of_no_return as u8],
+            "cdur" => meta.cdur.to_le_bytes().to_vec(),
+            "playtime" => meta.playtime.to_le_bytes().to_vec(),
+            "type" => Self::encode_string(meta.save_type.to_str()),
+            "description" => Self::encode_string(&meta.description),
+            "nexussessionid" => meta.nexus_session_id.to_le_bytes().to_vec(),
+            "sessionid" => Self::encode_string(&meta.session_id),
+            "buildcl" => meta.buildcl.to_le_bytes().to_vec(),
+            "unixtimeseconds" => meta.unix_timestamp.to_le_bytes().to_vec(),
+            "time" => Self::encode_string(&meta.date_time.to_rfc3339_opts(SecondsFormat::Secs, true)),
+            "expansion" => meta.expansion.to_vec(),
+            "savefileversion" => meta.save_file_version.to_le_bytes().to_vec(),
+            "project" => meta.project.to_le_bytes().to_vec(),
+            "licenseeversion" => {
+                if meta.licensee_version.len() != 1 {
+                    return Err(format!("licensee_version must be 1 byte, got {:?}", meta.licensee_version).into());
+                }
+                meta.licensee_version.as_bytes().to_vec()
+            }
+            "version" => {
+                let v = if occurrence == 0 { meta.version } else { meta.version_two };
+                v.to_le_bytes().to_vec()
+            }
+            "uid" => meta.guid.to_bytes_le().to_vec(),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(value))
+    }
+
+    // Only entries whose field changed between the two are re-encoded, everything else keeps its raw bytes.
+    pub fn apply_metadata(&mut self, original: &BWSaveGameMetadata, meta: &BWSaveGameMetadata) -> Result<(), Box<dyn Error>> {
+        let mut version_count = 0;
+
+        for entry in self.entries.iter_mut() {
+            if entry.tag == 0x00 {
+                continue;
+            }
+
+            let occurrence = if entry.name == "version" {
+                version_count += 1;
+                version_count - 1
+            } else {
+                0
+            };
+
+            let Some(new_value) = Self::encode_value(meta, &entry.name, occurrence)? else {
+                continue;
+            };
+            let old_value = Self::encode_value(original, &entry.name, occurrence)?;
+
+            if old_value.as_ref() != Some(&new_value) {
+                entry.value = new_value;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn serialize(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut body = Vec::new();
+
+        for entry in &self.entries {
+            body.push(entry.tag);
+            if entry.tag == 0x00 {
+                continue;
+            }
+
+            if entry.tag & 0x80 == 0 {
+                body.extend_from_slice(entry.name.as_bytes());
+                body.push(0x00);
+            }
+            body.extend_from_slice(&entry.value);
+        }
+
+        // The stored size isn't assumed to be the body length, it moves by as much as the body did.
+        let mut data = self.prefix.clone();
+        if body.len() != self.orig_body_len {
+            let (orig_size, _) = data.get(1..)
+                .and_then(varint::decode)
+                .ok_or("metadata prefix has no valid size")?;
+
+            let size = orig_size as i64 + body.len() as i64 - self.orig_body_len as i64;
+            let size = u32::try_from(size)
+                .map_err(|_| format!("metadata size {} is out of range", size))?;
+
+            varint::rewrite(&mut data, 1, size)
+                .ok_or("metadata prefix has no valid size")?;
+        }
+
+        data.extend_from_slice(&body);
+        Ok(data)
+    }
+}
+
+// Raw numbers are accepted too so values without a variant can still be set.
+fn parse_enum<T>(enum_name: &str, value: &str, from_name: fn(&str) -> Option<T>, from_u32: fn(u32) -> T) -> Result<T, Box<dyn Error>> {
+    let v = from_name(value)
+        .or_else(|| value.parse().ok().map(from_u32))
+        .ok_or(format!("Unknown {}: {}", enum_name, value))?;
+    Ok(v)
+}
+
+impl BWSaveGameMetadata {
+    // Field names are the ones used in the dump-metadata JSON. Enums take the variant name or the raw number.
+    pub fn set_field(&mut self, field: &str, value: &str) -> Result<(), Box<dyn Error>> {
+        match field {
+            "faction" => self.faction = parse_enum("CharacterFaction", value, CharacterFaction::from_name, CharacterFaction::from_u32)?,
+            "lineage" => self.lineage = parse_enum("CharacterLineage", value, CharacterLineage::from_name, CharacterLineage::from_u32)?,
+            "arche_type" => self.arche_type = parse_enum("CharacterArchetype", value, CharacterArchetype::from_name, CharacterArchetype::from_u32)?,
+            "character_name" => self.character_name = value.to_string(),
+            "quest_id" => self.quest_id = value.parse()?,
+            "request_id" => self.request_id = value.parse()?,
+            "active_career" => self.active_career = value.parse()?,
+            "key_binding_profile" => self.key_binding_profile = parse_enum("KeyBindingProfile", value, KeyBindingProfile::from_name, KeyBindingProfile::from_u32)?,
+            "after_point_of_no_return" => self.after_point_of_no_return = value.parse()?,
+            "character_level" => self.character_level = value.parse()?,
+            "difficulty" => self.difficulty = parse_enum("Difficulty", value, Difficulty::from_name, Difficulty::from_u32)?,
+            "voice_tone" => self.voice_tone = parse_enum("CharacterVoiceTone", value, CharacterVoiceTone::from_name, CharacterVoiceTone::from_u32)?,
+            "voice" => self.voice = parse_enum("CharacterVoice", value, CharacterVoice::from_name, CharacterVoice::from_u32)?,
+            "pronouns" => self.pronouns = parse_enum("CharacterPronouns", value, CharacterPronouns::from_name, CharacterPronouns::from_u32)?,
+            "gender" => self.gender = parse_enum("CharacterGender", value, CharacterGender::from_name, CharacterGender::from_u32)?,
+            "transition_point_name" => self.transition_point_name = value.to_string(),
+            "version_two" => self.version_two = value.parse()?,
+            "project_data" => self.project_data = value.parse()?,
+            "post_streaming_install" => self.post_streaming_install = value.parse()?,
+            "cdur" => self.cdur = value.parse()?,
+            "playtime" => self.playtime = value.parse()?,
+            "save_type" => self.save_type = SaveType::from_name(value)
+                .unwrap_or_else(|| SaveType::from_str(value)),
+            "description" => self.description = value.to_string(),
+            "nexus_session_id" => self.nexus_session_id = value.parse()?,
+            "session_id" => self.session_id = value.to_string(),
+            "buildcl" => self.buildcl = value.parse()?,
+            "unix_timestamp" => self.unix_timestamp = value.parse()?,
+            // Unix seconds like in the JSON, or RFC 3339.
+            "date_time" => {
+                self.date_time = match value.parse::<i64>() {
+                    Ok(secs) => DateTime::from_timestamp(secs, 0)
+                        .ok_or(format!("Invalid timestamp: {}", secs))?,
+                    Err(_) => DateTime::parse_from_rfc3339(value)?.with_timezone(&Utc),
+                };
+            }
+            // Comma separated bytes.
+            "expansion" => {
+                let bytes = value.split(',')
+                    .map(|b| b.trim().parse::<u8>())
+                    .collect::<Result<Vec<u8>, _>>()?;
+                self.expansion = bytes.as_slice().try_into()
+                    .map_err(|_| format!("expansion needs 9 bytes, got {}", bytes.len()))?;
+            }
+            "save_file_version" => self.save_file_version = value.parse()?,
+            "project" => self.project = value.parse()?,
+            "licensee_version" => self.licensee_version = value.to_string(),
+            "version" => self.version = value.parse()?,
+            "guid" => self.guid = Uuid::parse_str(value)?,
+            "checkpoint_id" => self.checkpoint_id = value.parse()?,
+            _ => return Err(format!("Unknown metadata field: {}", field).into()),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bw_save_game_metadata_reader::structs::BWSaveGameMetadataReader;
+    use crate::test_data::{block_one, metadata_entries};
+    use super::*;
+
+    fn edit(data: Vec<u8>, edit: impl Fn(&mut BWSaveGameMetadata)) -> Vec<u8> {
+        let mut r = BWSaveGameMetadataReader::new(data);
+        r.parse_metadata().unwrap();
+
+        let mut meta = r.metadata.clone();
+        edit(&mut meta);
+
+        let mut w = BWSaveGameMetadataWriter::new(r.prefix.clone(), r.entries.clone());
+        w.apply_metadata(&r.metadata, &meta).unwrap();
+        w.serialize().unwrap()
+    }
+
+    fn stored_size(data: &[u8]) -> u32 {
+        varint::decode(&data[1..]).unwrap().0
+    }
+
+    #[test]
+    fn unchanged_metadata_is_byte_identical() {
+        let data = block_one(&metadata_entries(), 0, 1);
+        assert_eq!(edit(data.clone(), |_| {}), data);
+    }
+
+    #[test]
+    fn edited_entry_is_written_and_the_size_follows() {
+        let data = block_one(&metadata_entries(), 0, 1);
+        let new_data = edit(data.clone(), |meta| {
+            meta.description = "Before Minrathous".to_string();
+            meta.difficulty = Difficulty::from_u32(3);
+        });
+
+        let mut r = BWSaveGameMetadataReader::new(new_data.clone());
+        r.parse_metadata().unwrap();
+        assert_eq!(r.metadata.description, "Before Minrathous");
+        assert_eq!(r.metadata.difficulty.to_u32(), 3);
+        assert_eq!(r.metadata.character_level, 6);
+        assert_eq!(r.entries[3].value, 7u32.to_le_bytes());
+
+        assert_eq!(stored_size(&new_data), stored_size(&data) + 10);
+        assert_eq!(new_data.len(), data.len() + 10);
+    }
+
+    #[test]
+    fn size_that_differs_from_the_body_keeps_its_difference() {
+        let data = block_one(&metadata_entries(), 3, 1);
+        let new_data = edit(data.clone(), |meta| meta.description = "Save".to_string());
+
+        assert_eq!(stored_size(&new_data), stored_size(&data) - 3);
+    }
+
+    #[test]
+    fn padded_size_keeps_its_width() {
+        let data = block_one(&metadata_entries(), 0, 3);
+        let new_data = edit(data.clone(), |meta| meta.description = "Save".to_string());
+
+        assert_eq!(varint::decode(&new_data[1..]).unwrap().1, 3);
+        assert_eq!(new_data.len(), data.len() - 3);
+        assert_eq!(edit(data.clone(), |_| {}), data);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod bw_save_game_metadata_writer;
+
+pub mod structs;
@@ -0,0 +1,7 @@
+use crate::bw_save_game_metadata_reader::structs::MetadataEntry;
+
+pub struct BWSaveGameMetadataWriter {
+    pub(crate) prefix: Vec<u8>,
+    pub(crate) orig_body_len: usize,
+    pub entries: Vec<MetadataEntry>,
+}
@@ -1,38 +1,79 @@
-use std::error::Error;
 use std::io;
-use std::io::{BufReader, Read, Seek};
-use flate2::bufread::GzDecoder;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use flate2::read::GzDecoder;
 use crate::bw_save_game_reader::structs::*;
+use crate::error::{BWSaveGameError, ErrorBlock};
 
-pub(crate) const MAGIC: &[u8; 8] = b"<!--DASC";
+pub const MAGIC: &[u8; 8] = b"<!--DASC";
+
+pub const HEADER_SIZE: u64 = 56;
+
+// Deflate can't do better than about 1032:1, anything above that is a broken header.
+const MAX_COMP_RATIO: u64 = 1032;
+
+// Smallest possible gzip stream, 10 byte header + empty deflate block + 8 byte trailer.
+const MIN_GZIP_SIZE: u64 = 20;
+
+// Most the decompressed size from the header is trusted for up front, past this the buffer grows as it's read.
+const MAX_PREALLOC_SIZE: u64 = 64 * 1024 * 1024;
 
-// Vec buf needed because of dyn sizes.
 impl<R: Read + Seek> BWSaveGameReader<R> {
-    pub(crate) fn new(f: R) -> Self {
+    pub fn new(f: R) -> Self {
         Self {
             f: BufReader::new(f),
-            // 1 MB just in case.
-            buffer: vec![0; 1024 * 1024],
+            buffer: Vec::new(),
             header: Header::default(),
-            block_one_data: Vec::new(),
-            block_two_data: Vec::new(),
+            block_one: Block::default(),
+            block_two: Block::default(),
         }
     }
 
-    pub(crate) fn read_bytes(&mut self, count: usize) -> io::Result<&[u8]> {
-        self.f.read_exact(&mut self.buffer[..count])?;
+    fn position(&mut self, block: ErrorBlock) -> Result<u64, BWSaveGameError> {
+        self.f.stream_position()
+            .map_err(|e| BWSaveGameError::io(block, 0, e))
+    }
+
+    // Only used for header fields, the blocks are streamed from the file.
+    pub(crate) fn read_bytes(&mut self, count: usize) -> Result<&[u8], BWSaveGameError> {
+        let offset = self.position(ErrorBlock::Header)?;
+
+        if self.buffer.len() < count {
+            self.buffer.resize(count, 0);
+        }
+
+        self.f.read_exact(&mut self.buffer[..count])
+            .map_err(|e| BWSaveGameError::io(ErrorBlock::Header, offset, e))?;
         Ok(&self.buffer[..count])
     }
 
-    pub(crate) fn read_header(&mut self) -> Result<(), Box<dyn Error>> {
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], BWSaveGameError> {
+        let mut arr = [0; N];
+        arr.copy_from_slice(self.read_bytes(N)?);
+        Ok(arr)
+    }
+
+    fn get_stream_len(&mut self) -> Result<u64, BWSaveGameError> {
+        let original_pos = self.position(ErrorBlock::Header)?;
+
+        let res = self.f.seek(SeekFrom::End(0))
+            .and_then(|len| self.f.seek(SeekFrom::Start(original_pos)).map(|_| len));
+
+        res.map_err(|e| BWSaveGameError::io(ErrorBlock::Header, original_pos, e))
+    }
+
+    pub fn read_header(&mut self) -> Result<(), BWSaveGameError> {
         let buf = self.read_bytes(8)?;
         if buf != MAGIC {
-            return Err("bad header magic".into());
+            return Err(BWSaveGameError::BadMagic {
+                block: ErrorBlock::Header,
+                offset: 0,
+                found: buf.to_vec(),
+            });
         }
 
         let version = self.read_u32_le()?;
 
-        let unk_001 = self.read_bytes(4)?.try_into()?;
+        let unk_001 = self.read_array()?;
 
         let block_two_decomp_size = self.read_u64_le()?;
         let block_two_comp_size = self.read_u64_le()?;
@@ -40,7 +81,7 @@ impl<R: Read + Seek> BWSaveGameReader<R> {
         let block_one_decomp_size = self.read_u64_le()?;
         let block_one_comp_size = self.read_u64_le()?;
 
-        let unk_002 = self.read_bytes(8)?.try_into()?;
+        let unk_002 = self.read_array()?;
 
         let header = Header {
             version,
@@ -52,49 +93,139 @@ impl<R: Read + Seek> BWSaveGameReader<R> {
             block_one_comp_size,
         };
 
+        let file_len = self.get_stream_len()?;
+        Self::check_header_sizes(&header, file_len)?;
+
         self.header = header;
         Ok(())
     }
 
-    pub(crate) fn read_u32_le(&mut self) -> Result<u32, Box<dyn Error>> {
-        let buf = self.read_bytes(4)?;
-        let arr: [u8; 4] = buf.try_into()?;
-        Ok(u32::from_le_bytes(arr))
+    fn check_header_sizes(header: &Header, file_len: u64) -> Result<(), BWSaveGameError> {
+        // Name, offset of the decomp size field, comp size, decomp size.
+        let blocks = [
+            ("block one", 32, header.block_one_comp_size, header.block_one_decomp_size),
+            ("block two", 16, header.block_two_comp_size, header.block_two_decomp_size),
+        ];
+
+        for (name, offset, comp_size, decomp_size) in blocks {
+            if comp_size < MIN_GZIP_SIZE {
+                return Err(BWSaveGameError::SizeMismatch {
+                    block: ErrorBlock::Header,
+                    offset: offset + 8,
+                    what: format!("{} compressed size is too small to be a gzip stream", name),
+                    expected: MIN_GZIP_SIZE,
+                    actual: comp_size,
+                });
+            }
+
+            let max_decomp_size = comp_size.saturating_mul(MAX_COMP_RATIO);
+            if decomp_size > max_decomp_size {
+                return Err(BWSaveGameError::SizeMismatch {
+                    block: ErrorBlock::Header,
+                    offset,
+                    what: format!("{} decompressed size is impossible for its compressed size", name),
+                    expected: max_decomp_size,
+                    actual: decomp_size,
+                });
+            }
+        }
+
+        let blocks_size = header.block_one_comp_size.saturating_add(header.block_two_comp_size);
+        let available = file_len.saturating_sub(HEADER_SIZE);
+        if blocks_size > available {
+            return Err(BWSaveGameError::SizeMismatch {
+                block: ErrorBlock::Header,
+                offset: 16,
+                what: "compressed block sizes are bigger than the rest of the file".to_string(),
+                expected: available,
+                actual: blocks_size,
+            });
+        }
+
+        Ok(())
     }
 
-    pub(crate) fn read_u64_le(&mut self) -> Result<u64, Box<dyn Error>> {
-        let buf = self.read_bytes(8)?;
-        let arr: [u8; 8] = buf.try_into()?;
+    pub(crate) fn read_u32_le(&mut self) -> Result<u32, BWSaveGameError> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
 
-        Ok(u64::from_le_bytes(arr))
+    pub(crate) fn read_u64_le(&mut self) -> Result<u64, BWSaveGameError> {
+        Ok(u64::from_le_bytes(self.read_array()?))
     }
 
-    pub(crate) fn read_block_one_data(&mut self) -> io::Result<()> {
-        let comp_size = self.header.block_one_comp_size as usize;
-        let decomp_size = self.header.block_one_decomp_size as usize;
+    fn read_block(&mut self, block: ErrorBlock, comp_size: u64, decomp_size: u64) -> Result<Block, BWSaveGameError> {
+        let offset = self.position(block)?;
 
-        let comp_data = self.read_bytes(comp_size)?;
+        let mut comp_data = Vec::with_capacity(comp_size as usize);
+        let mut tee = TeeReader {
+            inner: (&mut self.f).take(comp_size),
+            copy: &mut comp_data,
+        };
 
-        let mut decomp_data = vec![0; decomp_size];
-        let mut decoder = GzDecoder::new(&comp_data[..]);
-        decoder.read_exact(&mut decomp_data)?;
+        // Reading to the end also checks the gzip CRC and size trailer. Only one byte more than
+        // the header says is kept, the rest of an oversized stream is just counted.
+        let mut decomp_data = Vec::with_capacity(decomp_size.min(MAX_PREALLOC_SIZE) as usize);
+        let mut decoder = GzDecoder::new(&mut tee);
+        let res = (&mut decoder)
+            .take(decomp_size.saturating_add(1))
+            .read_to_end(&mut decomp_data)
+            .and_then(|len| Ok(len as u64 + io::copy(&mut decoder, &mut io::sink())?));
+        let gz_header = decoder.header().cloned();
+        drop(decoder);
 
-        self.block_one_data = decomp_data;
+        // Anything after the gzip trailer still belongs to the block.
+        io::copy(&mut tee, &mut io::sink())
+            .map_err(|e| BWSaveGameError::io(block, offset, e))?;
 
-        Ok(())
+        if comp_data.len() as u64 != comp_size {
+            return Err(BWSaveGameError::SizeMismatch {
+                block,
+                offset,
+                what: "compressed data is truncated".to_string(),
+                expected: comp_size,
+                actual: comp_data.len() as u64,
+            });
+        }
+
+        let decomp_len = res.map_err(|e| BWSaveGameError::Decompression {
+            block,
+            offset,
+            source: e,
+        })?;
+
+        if decomp_len != decomp_size {
+            return Err(BWSaveGameError::SizeMismatch {
+                block,
+                offset,
+                what: "decompressed size doesn't match the header".to_string(),
+                expected: decomp_size,
+                actual: decomp_len,
+            });
+        }
+
+        let block = Block {
+            data: decomp_data,
+            comp_data,
+            gz_header,
+        };
+
+        Ok(block)
     }
 
-    pub(crate) fn read_block_two_data(&mut self) -> io::Result<()> {
-        let comp_size = self.header.block_two_comp_size as usize;
-        let decomp_size = self.header.block_two_decomp_size as usize;
+    pub fn read_block_one_data(&mut self) -> Result<(), BWSaveGameError> {
+        let comp_size = self.header.block_one_comp_size;
+        let decomp_size = self.header.block_one_decomp_size;
 
-        let comp_data = self.read_bytes(comp_size)?;
+        self.block_one = self.read_block(ErrorBlock::BlockOne, comp_size, decomp_size)?;
+
+        Ok(())
+    }
 
-        let mut decomp_data = vec![0; decomp_size];
-        let mut decoder = GzDecoder::new(&comp_data[..]);
-        decoder.read_exact(&mut decomp_data)?;
+    pub fn read_block_two_data(&mut self) -> Result<(), BWSaveGameError> {
+        let comp_size = self.header.block_two_comp_size;
+        let decomp_size = self.header.block_two_decomp_size;
 
-        self.block_two_data = decomp_data;
+        self.block_two = self.read_block(ErrorBlock::BlockTwo, comp_size, decomp_size)?;
 
         Ok(())
     }
@@ -227,4 +358,105 @@ impl<R: Read + Seek> BWSaveGameReader<R> {
     //     Ok(obj)
     // }
 
+}
+
+impl<R: Read> Read for TeeReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.copy.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use super::*;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn container(block_one: &[u8], block_two: &[u8]) -> Vec<u8> {
+        let (comp_one, comp_two) = (gzip(block_one), gzip(block_two));
+
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(&[0; 4]);
+        for size in [block_two.len(), comp_two.len(), block_one.len(), comp_one.len()] {
+            data.extend_from_slice(&(size as u64).to_le_bytes());
+        }
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&comp_one);
+        data.extend_from_slice(&comp_two);
+        data
+    }
+
+    fn read(data: Vec<u8>) -> Result<BWSaveGameReader<Cursor<Vec<u8>>>, BWSaveGameError> {
+        let mut r = BWSaveGameReader::new(Cursor::new(data));
+        r.read_header()?;
+        r.read_block_one_data()?;
+        r.read_block_two_data()?;
+        Ok(r)
+    }
+
+    #[test]
+    fn reads_blocks_bigger_than_one_mib() {
+        let block_two: Vec<u8> = (0..3 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        let data = container(b"block one", &block_two);
+
+        let r = read(data.clone()).unwrap();
+        assert_eq!(r.block_one.data, b"block one");
+        assert_eq!(r.block_two.data, block_two);
+
+        let comp_one_end = HEADER_SIZE as usize + r.header.block_one_comp_size as usize;
+        assert_eq!(r.block_one.comp_data, &data[HEADER_SIZE as usize..comp_one_end]);
+        assert_eq!(r.block_two.comp_data, &data[comp_one_end..]);
+    }
+
+    #[test]
+    fn wrong_decompressed_size_is_an_error() {
+        let mut data = container(b"block one", b"block two");
+        data[32..40].copy_from_slice(&10u64.to_le_bytes());
+
+        let err = read(data).err().unwrap();
+        assert!(matches!(
+            err,
+            BWSaveGameError::SizeMismatch { block: ErrorBlock::BlockOne, expected: 10, actual: 9, .. },
+        ));
+    }
+
+    #[test]
+    fn stream_bigger_than_the_header_says_is_a_size_mismatch() {
+        let block_two = vec![0u8; 1024 * 1024];
+        let mut data = container(b"block one", &block_two);
+        data[16..24].copy_from_slice(&1000u64.to_le_bytes());
+
+        let err = read(data).err().unwrap();
+        assert!(matches!(
+            err,
+            BWSaveGameError::SizeMismatch { block: ErrorBlock::BlockTwo, expected: 1000, actual: 1_048_576, .. },
+        ));
+    }
+
+    #[test]
+    fn bad_magic_is_an_error() {
+        let mut data = container(b"block one", b"block two");
+        data[0] = b'X';
+
+        let err = read(data).err().unwrap();
+        assert!(matches!(err, BWSaveGameError::BadMagic { .. }));
+    }
+
+    #[test]
+    fn short_header_is_an_io_error_with_its_offset() {
+        let data = container(b"block one", b"block two")[..20].to_vec();
+
+        let err = read(data).err().unwrap();
+        assert!(matches!(err, BWSaveGameError::Io { block: ErrorBlock::Header, offset: 16, .. }));
+    }
 }
\ No newline at end of file
@@ -1,21 +1,36 @@
 use std::io::{BufReader, Read, Seek};
+use flate2::GzHeader;
 
 pub struct BWSaveGameReader<R: Read + Seek> {
     pub(crate) f: BufReader<R>,
     pub(crate) buffer: Vec<u8>,
-    pub(crate) header: Header,
-    pub(crate) block_one_data: Vec<u8>,
-    pub(crate) block_two_data: Vec<u8>,
+    pub header: Header,
+    pub block_one: Block,
+    pub block_two: Block,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Header {
-    #[allow(dead_code)]
-    pub(crate) version: u32,
-    pub(crate) _unk_001: [u8; 4],
-    pub(crate) _unk_002: [u8; 8],
-    pub(crate) block_one_comp_size: u64,
-    pub(crate) block_one_decomp_size: u64,
-    pub(crate) block_two_comp_size: u64,
-    pub(crate) block_two_decomp_size: u64,
+    pub version: u32,
+    pub _unk_001: [u8; 4],
+    pub _unk_002: [u8; 8],
+    pub block_one_comp_size: u64,
+    pub block_one_decomp_size: u64,
+    pub block_two_comp_size: u64,
+    pub block_two_decomp_size: u64,
+}
+
+// Comp data and gzip header are kept so unmodified blocks can be written back byte for byte.
+#[derive(Default, Clone)]
+pub struct Block {
+    pub data: Vec<u8>,
+    pub comp_data: Vec<u8>,
+    pub gz_header: Option<GzHeader>,
+}
+
+// Keeps a copy of everything read through it, so a block can be decompressed straight from
+// the file and still be written back byte for byte.
+pub(crate) struct TeeReader<'a, R: Read> {
+    pub(crate) inner: R,
+    pub(crate) copy: &'a mut Vec<u8>,
 }
\ No newline at end of file
@@ -0,0 +1,165 @@
+use std::fmt;
+use std::io::Read;
+use flate2::Crc;
+use flate2::bufread::GzDecoder;
+use crate::bw_save_game_client_data_reader::structs::BWSaveGameClientDataReader;
+use crate::bw_save_game_metadata_reader::structs::BWSaveGameMetadataReader;
+use crate::bw_save_game_reader::bw_save_game_reader::{HEADER_SIZE, MAGIC};
+use crate::bw_save_game_verifier::structs::*;
+
+// Works on the raw file so one broken part doesn't stop the rest from being checked.
+impl BWSaveGameVerifier {
+    pub fn new(data: Vec<u8>) -> Self {
+        Self {
+            data,
+            report: VerifyReport::default(),
+        }
+    }
+
+    fn check(&mut self, name: &str, passed: bool, detail: String) -> bool {
+        self.report.checks.push(VerifyCheck {
+            name: name.to_string(),
+            passed,
+            detail,
+        });
+        passed
+    }
+
+    fn read_u64_le(&self, offset: usize) -> u64 {
+        let arr: [u8; 8] = self.data[offset..offset + 8].try_into().unwrap();
+        u64::from_le_bytes(arr)
+    }
+
+    fn read_u32_le(&self, offset: usize) -> u32 {
+        let arr: [u8; 4] = self.data[offset..offset + 4].try_into().unwrap();
+        u32::from_le_bytes(arr)
+    }
+
+    // Returns the decompressed data if the stream could be decoded.
+    fn verify_block(&mut self, name: &str, offset: usize, comp_size: u64, decomp_size: u64) -> Option<Vec<u8>> {
+        let end = (offset as u64).saturating_add(comp_size);
+        let in_bounds = end <= self.data.len() as u64;
+        let detail = format!("{:#X}..{:#X}, file is {:#X} bytes", offset, end, self.data.len());
+        if !self.check(&format!("{} within file", name), in_bounds, detail) {
+            return None;
+        }
+
+        let end = end as usize;
+
+        // Decode without the header's size limit to find where the stream really ends.
+        let mut decomp_data = Vec::new();
+        let mut decoder = GzDecoder::new(&self.data[offset..]);
+        let res = decoder.read_to_end(&mut decomp_data);
+        let comp_len = (self.data.len() - offset - decoder.into_inner().len()) as u64;
+
+        let decoded = match res {
+            Ok(_) => self.check(&format!("{} decompresses", name), true, format!("{} bytes", decomp_data.len())),
+            Err(e) => self.check(&format!("{} decompresses", name), false, e.to_string()),
+        };
+
+        if decoded {
+            let detail = format!("header {}, stream {}", comp_size, comp_len);
+            self.check(&format!("{} compressed size", name), comp_len == comp_size, detail);
+
+            let detail = format!("header {}, stream {}", decomp_size, decomp_data.len());
+            self.check(&format!("{} decompressed size", name), decomp_data.len() as u64 == decomp_size, detail);
+        }
+
+        // The trailer at the end of the header's range, CRC32 then ISIZE.
+        if comp_size >= 8 {
+            let mut crc = Crc::new();
+            crc.update(&decomp_data);
+
+            let trailer_crc = self.read_u32_le(end - 8);
+            let detail = format!("trailer {:#010X}, data {:#010X}", trailer_crc, crc.sum());
+            self.check(&format!("{} CRC", name), trailer_crc == crc.sum(), detail);
+        }
+
+        if decoded {
+            Some(decomp_data)
+        } else {
+            None
+        }
+    }
+
+    pub fn verify(&mut self) -> &VerifyReport {
+        let has_magic = self.data.len() >= MAGIC.len() && &self.data[..MAGIC.len()] == MAGIC;
+        self.check("magic", has_magic, String::from_utf8_lossy(MAGIC).to_string());
+
+        let has_header = self.data.len() as u64 >= HEADER_SIZE;
+        let detail = format!("{} of {} bytes", self.data.len().min(HEADER_SIZE as usize), HEADER_SIZE);
+        if !self.check("header", has_magic && has_header, detail) {
+            return &self.report;
+        }
+
+        let block_two_decomp_size = self.read_u64_le(16);
+        let block_two_comp_size = self.read_u64_le(24);
+        let block_one_decomp_size = self.read_u64_le(32);
+        let block_one_comp_size = self.read_u64_le(40);
+
+        let block_one_offset = HEADER_SIZE as usize;
+        let block_two_offset = block_one_offset.saturating_add(block_one_comp_size as usize);
+
+        let block_one_data = self.verify_block("block one", block_one_offset, block_one_comp_size, block_one_decomp_size);
+        let block_two_data = self.verify_block("block two", block_two_offset, block_two_comp_size, block_two_decomp_size);
+
+        let expected_len = HEADER_SIZE
+            .saturating_add(block_one_comp_size)
+            .saturating_add(block_two_comp_size);
+        let detail = format!("expected {} bytes, file is {}", expected_len, self.data.len());
+        self.check("no trailing data", expected_len == self.data.len() as u64, detail);
+
+        match block_one_data {
+            Some(data) => {
+                let mut r = BWSaveGameMetadataReader::new(data);
+                match r.parse_metadata() {
+                    Ok(_) => self.check("block one metadata", true, r.metadata.description.clone()),
+                    Err(e) => self.check("block one metadata", false, e.to_string()),
+                };
+            }
+            None => {
+                self.check("block one metadata", false, "skipped, block one didn't decompress".to_string());
+            }
+        }
+
+        match block_two_data {
+            Some(data) => {
+                let mut r = BWSaveGameClientDataReader::new(data);
+                match r.read_char_data() {
+                    Ok(_) => self.check("block two chunks", true, format!("char data {} bytes at {:#X}", r.char_data.len(), r.char_data_offset)),
+                    Err(e) => self.check("block two chunks", false, e.to_string()),
+                };
+
+                match r.read_contrib_section() {
+                    Ok(_) => self.check("block two contrib section", true, format!("{} entries, {} bytes", r.contrib.entries.len(), r.contrib.size)),
+                    Err(e) => self.check("block two contrib section", false, e.to_string()),
+                };
+            }
+            None => {
+                self.check("block two chunks", false, "skipped, block two didn't decompress".to_string());
+            }
+        }
+
+        &self.report
+    }
+}
+
+impl VerifyReport {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|c| c.passed)
+    }
+
+    pub fn failed_count(&self) -> usize {
+        self.checks.iter().filter(|c| !c.passed).count()
+    }
+}
+
+impl fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            let status = if check.passed { "PASS" } else { "FAIL" };
+            writeln!(f, "[{}] {}: {}", status, check.name, check.detail)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,3 @@
+pub mod bw_save_game_verifier;
+
+pub mod structs;
@@ -0,0 +1,15 @@
+pub struct BWSaveGameVerifier {
+    pub(crate) data: Vec<u8>,
+    pub report: VerifyReport,
+}
+
+#[derive(Default)]
+pub struct VerifyReport {
+    pub checks: Vec<VerifyCheck>,
+}
+
+pub struct VerifyCheck {
+    pub name: String,
+    pub passed: bool,
+    pub detail: String,
+}
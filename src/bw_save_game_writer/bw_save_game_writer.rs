@@ -1,140 +1,292 @@
 use std::error::Error;
-use std::fs::File;
-use std::{env, fs, io};
-use std::io::{BufWriter, Write};
-use std::path::PathBuf;
-use flate2::Compression;
+use std::fs::{File, OpenOptions};
+use std::{fs, io};
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use flate2::{Compression, GzBuilder};
+use flate2::bufread::GzDecoder;
 use flate2::write::GzEncoder;
+use uuid::Uuid;
+use crate::bw_save_game_client_data_reader::enums::AppearanceTarget;
 use crate::bw_save_game_client_data_reader::structs::BWSaveGameClientDataReader;
-use crate::bw_save_game_reader::structs::BWSaveGameReader;
+use crate::bw_save_game_reader::structs::{BWSaveGameReader, Block, Header};
 use crate::bw_save_game_reader::bw_save_game_reader::MAGIC;
 use crate::bw_save_game_writer::structs::BWSaveGameWriter;
+use crate::varint;
 
 
 impl BWSaveGameWriter<File> {
-    pub(crate) fn new() -> io::Result<Self> {
-        let temp_path = env::temp_dir().join("davst_temp.bin");
-        let f = File::create(&temp_path)?;
+    // The temp file sits next to the output save so the final rename never crosses filesystems.
+    pub fn new(out_path: &Path) -> io::Result<Self> {
+        let out_dir = Self::out_dir(out_path);
+        let out_fname = out_path.file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "output save path has no file name"))?;
+
+        let temp_path = out_dir.join(format!(".{}.{}.tmp", out_fname.to_string_lossy(), Uuid::new_v4()));
+        let f = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
         let w = BufWriter::new(f);
 
-        Ok(Self { w, temp_path })
+        Ok(Self {
+            w: Some(w),
+            temp_path,
+            out_path: out_path.to_path_buf(),
+        })
     }
 
-    fn shift_chunk_size(size: u32) -> Vec<u8> {
-        let mut bytes = Vec::new();
-        let mut value = size;
+    // Swaps the dest save's character data chunk for char_data, marker and size included.
+    // With a single character as the target only that character's record is swapped.
+    // The result goes to the writer's output path, which can be the dest save itself.
+    pub fn inject_char_data(mut self, dest_save_path: &Path, char_data: &[u8], target: AppearanceTarget) -> Result<(), Box<dyn Error>> {
+        if let Err(e) = self.write_injected(dest_save_path, char_data, target) {
+            self.discard();
+            return Err(e);
+        }
 
-        loop {
-            let byte = (value & 0x7F) as u8;
-            value >>= 7;
+        self.save()?;
 
-            if value > 0 {
-                bytes.push(byte | 0x80);
-            } else {
-                bytes.push(byte);
-                break;
-            }
+        Ok(())
+    }
+
+    // Flushed and synced before the rename, so the output is either the old file or the
+    // complete new one. On any failure the temp file is removed and the output is left alone.
+    // The directory is synced after the rename so the rename itself survives a crash.
+    pub fn save(mut self) -> io::Result<()> {
+        let w = self.w.take().expect("writer is only emptied when it's consumed");
+
+        let res = w.into_inner()
+            .map_err(|e| e.into_error())
+            .and_then(|f| f.sync_all())
+            .and_then(|_| fs::rename(&self.temp_path, &self.out_path));
+
+        if res.is_err() {
+            let _ = fs::remove_file(&self.temp_path);
         }
+        res?;
 
-        bytes
+        Self::sync_dir(Self::out_dir(&self.out_path))
     }
 
-    fn compress(to_comp: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
-        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-        encoder.write_all(to_comp)?;
+    // Drops everything written so far without touching the output.
+    pub fn discard(self) {
+        drop(self);
+    }
 
-        let compressed_data = encoder.finish()?;
-        Ok(compressed_data)
+    fn out_dir(out_path: &Path) -> &Path {
+        match out_path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        }
+    }
+
+    // Windows can't open a directory to sync it, there the rename is left to the OS.
+    #[cfg(unix)]
+    fn sync_dir(dir: &Path) -> io::Result<()> {
+        File::open(dir)?.sync_all()
+    }
+
+    #[cfg(not(unix))]
+    fn sync_dir(_dir: &Path) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl BWSaveGameWriter<Vec<u8>> {
+    // For dry runs, nothing touches the disk.
+    pub fn in_memory() -> Self {
+        Self {
+            w: Some(BufWriter::new(Vec::new())),
+            temp_path: PathBuf::new(),
+            out_path: PathBuf::new(),
+        }
     }
 
-    pub(crate) fn rebuild(&mut self, dest_save_path: &PathBuf, src_block_two_data: Vec<u8>) ->  Result<(), Box<dyn Error>> {
-        let mut src_data_r = BWSaveGameClientDataReader::new(src_block_two_data);
-        src_data_r.read_char_data()?;
+    pub fn into_bytes(mut self) -> io::Result<Vec<u8>> {
+        self.w.take()
+            .expect("writer is only emptied when it's consumed")
+            .into_inner()
+            .map_err(|e| e.into_error())
+    }
+}
+
+impl<W: Write> BWSaveGameWriter<W> {
+    // Dest's character data chunk with one character's record taken from src instead.
+    fn merge_char_data(dest_char_data: &[u8], src_char_data: &[u8], target: AppearanceTarget) -> Result<Vec<u8>, Box<dyn Error>> {
+        if target == AppearanceTarget::Both {
+            return Ok(src_char_data.to_vec());
+        }
+
+        let dest_range = BWSaveGameClientDataReader::character_range(dest_char_data, target)?;
+        let src_range = BWSaveGameClientDataReader::character_range(src_char_data, target)?;
+        let delta = src_range.len() as i64 - dest_range.len() as i64;
+
+        let mut dest_r = BWSaveGameClientDataReader::new(dest_char_data.to_vec());
+        dest_r.read_chunk_tree()?;
+        let char_chunk = &dest_r.chunks[0];
+
+        let mut merged = dest_char_data.to_vec();
+        merged.splice(dest_range, src_char_data[src_range].iter().copied());
 
+        let size = (char_chunk.size as i64 + delta) as u32;
+        varint::rewrite(&mut merged, char_chunk.size_offset as usize, size)
+            .ok_or("chunk size isn't a valid varint")?;
+
+        Ok(merged)
+    }
+
+    // Writes the dest save with its character data swapped, see inject_char_data.
+    pub fn write_injected(&mut self, dest_save_path: &Path, char_data: &[u8], target: AppearanceTarget) -> Result<(), Box<dyn Error>> {
         {
-            let dest_f = File::open(&dest_save_path)?;
+            let dest_f = File::open(dest_save_path)?;
             let mut dest_r = BWSaveGameReader::new(dest_f);
 
             dest_r.read_header()?;
             dest_r.read_block_one_data()?;
             dest_r.read_block_two_data()?;
 
-            let dest_block_data = dest_r.block_two_data;
+            let dest_block_data = &dest_r.block_two.data;
             let mut dest_data_r = BWSaveGameClientDataReader::new(dest_block_data.clone());
             dest_data_r.read_char_data()?;
 
-            let head = dest_block_data[..dest_data_r.char_data_offset as usize].to_vec();
+            let char_data_start = dest_data_r.char_data_offset as usize;
+            let char_data_end = char_data_start + dest_data_r.char_data.len();
 
-            let end_of_char_data_offset = (dest_data_r.char_data_offset + dest_data_r.char_data_size as u64) + 4;
+            let mut final_data = dest_block_data.clone();
+            let mut layout = dest_data_r.layout.clone();
+            let char_data = Self::merge_char_data(&dest_data_r.char_data, char_data, target)?;
+            layout.splice(&mut final_data, char_data_start..char_data_end, &char_data)?;
 
-            let mut tail = dest_block_data[end_of_char_data_offset as usize..].to_vec();
+            let block_two = Block {
+                data: final_data,
+                ..dest_r.block_two.clone()
+            };
 
-            let tail_byte_needed = tail[1] == 0x02;
-            if  tail_byte_needed {
-                tail.insert(0, 0x0);
-            }
+            self.write_container(&dest_r.header, &dest_r.block_one, &block_two)?;
+        }
 
-            let mut final_data: Vec<u8> = Vec::new();
+        Ok(())
+    }
 
-            final_data.extend_from_slice(&head);
-            final_data.extend_from_slice(&src_data_r.char_data);
-            final_data.extend_from_slice(&tail);
+    // An unchanged block gets its original gzip stream back. Anything else is recompressed
+    // with the original gzip header fields (mtime, OS, name, etc.) where there are any.
+    fn compress_block(block: &Block) -> Result<Vec<u8>, Box<dyn Error>> {
+        if !block.comp_data.is_empty() {
+            let mut orig_data = Vec::new();
+            let mut decoder = GzDecoder::new(block.comp_data.as_slice());
+            decoder.read_to_end(&mut orig_data)?;
 
-            let mut server_offset = dest_data_r.server_offset as usize - dest_data_r.char_data_size as usize + src_data_r.char_data.len() - 4;
-            if tail_byte_needed {
-                server_offset += 1;
+            if orig_data == block.data {
+                return Ok(block.comp_data.clone());
             }
+        }
 
-            let mut final_size = final_data.len() - 4;
-            if dest_data_r.server_offset_is_two_bytes {
-                final_data.insert(12, 0x0);
-                final_data.insert(29, 0x0);
-                final_size += 2;
-                server_offset += 1;
+        let mut encoder = match &block.gz_header {
+            Some(gz_header) => {
+                let mut builder = GzBuilder::new()
+                    .mtime(gz_header.mtime())
+                    .operating_system(gz_header.operating_system());
+
+                if let Some(extra) = gz_header.extra() {
+                    builder = builder.extra(extra);
+                }
+                if let Some(filename) = gz_header.filename() {
+                    builder = builder.filename(filename);
+                }
+                if let Some(comment) = gz_header.comment() {
+                    builder = builder.comment(comment);
+                }
+
+                builder.write(Vec::new(), Compression::default())
             }
+            None => GzEncoder::new(Vec::new(), Compression::default()),
+        };
+        encoder.write_all(&block.data)?;
 
-            let server_data_offset_shifted = Self::shift_chunk_size(server_offset as u32);
-            let contrib_offset = server_offset - 18;
+        let compressed_data = encoder.finish()?;
+        Ok(compressed_data)
+    }
 
-            let contrib_shifted = Self::shift_chunk_size(contrib_offset as u32);
+    // Version and unknown fields are carried over from the header, the sizes are recomputed.
+    pub fn write_container(&mut self, header: &Header, block_one: &Block, block_two: &Block) -> Result<(), Box<dyn Error>> {
+        let block_one_comp_data = Self::compress_block(block_one)?;
+        let block_two_comp_data = Self::compress_block(block_two)?;
+        let w = self.w.as_mut().expect("writer is only emptied when it's consumed");
 
-            let size_bytes = Self::shift_chunk_size(final_size as u32);
+        w.write_all(MAGIC)?;
+        w.write_all(&header.version.to_le_bytes())?;
+        w.write_all(&header._unk_001)?;
 
-            final_data[1..4].copy_from_slice(&size_bytes);
-            final_data[12..15].copy_from_slice(&server_data_offset_shifted);
-            final_data[29..32].copy_from_slice(&contrib_shifted);
+        w.write_all(&(block_two.data.len() as u64).to_le_bytes())?;
+        w.write_all(&(block_two_comp_data.len() as u64).to_le_bytes())?;
+        w.write_all(&(block_one.data.len() as u64).to_le_bytes())?;
+        w.write_all(&(block_one_comp_data.len() as u64).to_le_bytes())?;
 
+        w.write_all(&header._unk_002)?;
 
-            self.w.write_all(MAGIC)?;
-            self.w.write_all(&[0x02, 0x00, 0x00, 0x00])?;
-            self.w.write_all(&dest_r.header._unk_001)?;
+        w.write_all(&block_one_comp_data)?;
+        w.write_all(&block_two_comp_data)?;
 
+        w.flush()?;
 
-            let block_one_comp_data = Self::compress(&dest_r.block_one_data)?;
-            let block_two_comp_data = Self::compress(&final_data)?;
+        Ok(())
+    }
+}
 
-            let block_two_uncomp_size_bytes: &[u8; 8] = &final_data.len().to_le_bytes();
-            self.w.write_all(block_two_uncomp_size_bytes)?;
+// A writer dropped before it's saved, e.g. on an early return, takes its temp file with it.
+impl<W: Write> Drop for BWSaveGameWriter<W> {
+    fn drop(&mut self) {
+        if let Some(w) = self.w.take() {
+            drop(w);
 
-            let block_two_comp_size_bytes: &[u8; 8] = &block_two_comp_data.len().to_le_bytes();
-            self.w.write_all(block_two_comp_size_bytes)?;
+            if !self.temp_path.as_os_str().is_empty() {
+                let _ = fs::remove_file(&self.temp_path);
+            }
+        }
+    }
+}
 
-            let block_one_uncomp_size_bytes: &[u8; 8] = &dest_r.header.block_one_decomp_size.to_le_bytes();
-            self.w.write_all(block_one_uncomp_size_bytes)?;
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use super::*;
 
-            let block_one_comp_size_bytes: &[u8; 8] = &block_one_comp_data.len().to_le_bytes();
-            self.w.write_all(block_one_comp_size_bytes)?;
+    fn temp_dir() -> PathBuf {
+        let dir = env::temp_dir().join(format!("davst-writer-{}", Uuid::new_v4()));
+        fs::create_dir(&dir).unwrap();
+        dir
+    }
 
-            self.w.write_all(&dest_r.header._unk_002)?;
+    fn entries(dir: &Path) -> Vec<PathBuf> {
+        fs::read_dir(dir).unwrap().map(|e| e.unwrap().path()).collect()
+    }
 
-            self.w.write_all(&block_one_comp_data)?;
-            self.w.write_all(&block_two_comp_data)?;
-        }
+    #[test]
+    fn save_leaves_only_the_output() {
+        let dir = temp_dir();
+        let out_path = dir.join("out.csav");
 
-        fs::remove_file(dest_save_path)?;
-        fs::copy(&self.temp_path, &dest_save_path)?;
+        let mut w = BWSaveGameWriter::new(&out_path).unwrap();
+        w.w.as_mut().unwrap().write_all(b"save").unwrap();
+        w.save().unwrap();
 
-        Ok(())
+        assert_eq!(entries(&dir), [out_path.as_path()]);
+        assert_eq!(fs::read(&out_path).unwrap(), b"save");
+        fs::remove_dir_all(dir).unwrap();
     }
 
+    #[test]
+    fn dropped_writer_removes_its_temp_file() {
+        let dir = temp_dir();
+
+        let mut w = BWSaveGameWriter::new(&dir.join("out.csav")).unwrap();
+        w.w.as_mut().unwrap().write_all(b"half a save").unwrap();
+        assert_eq!(entries(&dir), [w.temp_path.as_path()]);
+
+        drop(w);
+
+        assert!(entries(&dir).is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
\ No newline at end of file
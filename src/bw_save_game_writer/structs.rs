@@ -2,6 +2,8 @@ use std::io::{BufWriter, Write};
 use std::path::PathBuf;
 
 pub struct BWSaveGameWriter<W: Write> {
-    pub(crate) w: BufWriter<W>,
+    // Only None once the writer has been finished, Drop then leaves the temp file alone.
+    pub(crate) w: Option<BufWriter<W>>,
     pub(crate) temp_path: PathBuf,
+    pub(crate) out_path: PathBuf,
 }
\ No newline at end of file
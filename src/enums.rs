@@ -1,11 +1,39 @@
-use clap::ValueEnum;
+use clap::{Subcommand, ValueEnum};
+use crate::structs::{
+    DumpBlocksArgs, DumpChunksArgs, DumpMetadataArgs, EditMetadataArgs, ExportAppearanceArgs, ImportAppearanceArgs,
+    ImportMetadataArgs, InjectAppearanceArgs, PackBlocksArgs, RestoreArgs, VerifyArgs,
+};
 
-#[derive(ValueEnum, Clone, Debug)]
+#[derive(Subcommand, Clone, Debug)]
 pub enum Cmd {
-    Db,
-    DumpBlocks,
-    Dm,
-    DumpMetadata,
-    Ia,
-    InjectAppearance,
+    #[command(visible_alias="db", about="Decompress both blocks and write them out.")]
+    DumpBlocks(DumpBlocksArgs),
+    #[command(visible_alias="dm", about="Write the metadata block to a JSON file.")]
+    DumpMetadata(DumpMetadataArgs),
+    #[command(visible_alias="ia", about="Inject the appearance from one save into another.")]
+    InjectAppearance(InjectAppearanceArgs),
+    #[command(visible_alias="pb", about="Pack block files written by dump-blocks into a save.")]
+    PackBlocks(PackBlocksArgs),
+    #[command(visible_alias="vf", about="Check a save without modifying it.")]
+    Verify(VerifyArgs),
+    #[command(visible_alias="em", about="Change metadata fields and repack the save.")]
+    EditMetadata(EditMetadataArgs),
+    #[command(visible_alias="im", about="Write a metadata JSON file into a save.")]
+    ImportMetadata(ImportMetadataArgs),
+    #[command(visible_alias="dc", about="Print block two's chunk tree and server section.")]
+    DumpChunks(DumpChunksArgs),
+    #[command(visible_alias="ea", about="Write a save's appearance to a preset file.")]
+    ExportAppearance(ExportAppearanceArgs),
+    #[command(visible_alias="iap", about="Inject the appearance from a preset file into a save.")]
+    ImportAppearance(ImportAppearanceArgs),
+    #[command(visible_alias="rs", about="List a save's backups or put one back.")]
+    Restore(RestoreArgs),
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum Character {
+    Rook,
+    Inquisitor,
+    #[default]
+    Both,
 }
\ No newline at end of file
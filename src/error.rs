@@ -0,0 +1,169 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorBlock {
+    Header,
+    BlockOne,
+    BlockTwo,
+}
+
+impl fmt::Display for ErrorBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Header => "header",
+            Self::BlockOne => "block one",
+            Self::BlockTwo => "block two",
+        };
+        f.write_str(name)
+    }
+}
+
+// Header offsets are file offsets, block offsets are into the decompressed block data.
+#[derive(Debug)]
+pub enum BWSaveGameError {
+    BadMagic {
+        block: ErrorBlock,
+        offset: u64,
+        found: Vec<u8>,
+    },
+    SizeMismatch {
+        block: ErrorBlock,
+        offset: u64,
+        what: String,
+        expected: u64,
+        actual: u64,
+    },
+    Decompression {
+        block: ErrorBlock,
+        offset: u64,
+        source: io::Error,
+    },
+    UnknownMetadataKey {
+        block: ErrorBlock,
+        offset: u64,
+        name: String,
+    },
+    UnknownEnumValue {
+        block: ErrorBlock,
+        offset: u64,
+        enum_name: &'static str,
+        value: String,
+    },
+    BadChunkMarker {
+        block: ErrorBlock,
+        offset: u64,
+        marker: u8,
+    },
+    // A block two field whose value doesn't decode as the type its tag says.
+    BadFieldValue {
+        block: ErrorBlock,
+        offset: u64,
+        tag: u8,
+    },
+    // A value that's there but can't be read as its type, e.g. a string that isn't UTF-8.
+    BadValue {
+        block: ErrorBlock,
+        offset: u64,
+        what: String,
+    },
+    MissingChunk {
+        block: ErrorBlock,
+        offset: u64,
+        what: String,
+    },
+    Io {
+        block: ErrorBlock,
+        offset: u64,
+        source: io::Error,
+    },
+}
+
+impl BWSaveGameError {
+    pub(crate) fn io(block: ErrorBlock, offset: u64, source: io::Error) -> Self {
+        Self::Io {
+            block,
+            offset,
+            source,
+        }
+    }
+
+    pub fn block(&self) -> ErrorBlock {
+        match self {
+            Self::BadMagic { block, .. }
+            | Self::SizeMismatch { block, .. }
+            | Self::Decompression { block, .. }
+            | Self::UnknownMetadataKey { block, .. }
+            | Self::UnknownEnumValue { block, .. }
+            | Self::BadChunkMarker { block, .. }
+            | Self::BadFieldValue { block, .. }
+            | Self::BadValue { block, .. }
+            | Self::MissingChunk { block, .. }
+            | Self::Io { block, .. } => *block,
+        }
+    }
+
+    pub fn offset(&self) -> u64 {
+        match self {
+            Self::BadMagic { offset, .. }
+            | Self::SizeMismatch { offset, .. }
+            | Self::Decompression { offset, .. }
+            | Self::UnknownMetadataKey { offset, .. }
+            | Self::UnknownEnumValue { offset, .. }
+            | Self::BadChunkMarker { offset, .. }
+            | Self::BadFieldValue { offset, .. }
+            | Self::BadValue { offset, .. }
+            | Self::MissingChunk { offset, .. }
+            | Self::Io { offset, .. } => *offset,
+        }
+    }
+}
+
+impl fmt::Display for BWSaveGameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {:#X}: ", self.block(), self.offset())?;
+
+        match self {
+            Self::BadMagic { found, .. } => {
+                write!(f, "bad magic {:02X?}", found)
+            }
+            Self::SizeMismatch { what, expected, actual, .. } => {
+                write!(f, "{} (expected {}, got {})", what, expected, actual)
+            }
+            Self::Decompression { source, .. } => {
+                write!(f, "decompression failed: {}", source)
+            }
+            Self::UnknownMetadataKey { name, .. } => {
+                write!(f, "unknown metadata key: {}", name)
+            }
+            Self::UnknownEnumValue { enum_name, value, .. } => {
+                write!(f, "unknown {} value: {}", enum_name, value)
+            }
+            Self::BadChunkMarker { marker, .. } => {
+                write!(f, "bad chunk start marker byte {:#04X}", marker)
+            }
+            Self::BadFieldValue { tag, .. } => {
+                write!(f, "field value doesn't decode as tag {:#04X}", tag)
+            }
+            Self::BadValue { what, .. } => {
+                write!(f, "bad value: {}", what)
+            }
+            Self::MissingChunk { what, .. } => {
+                write!(f, "missing chunk: {}", what)
+            }
+            Self::Io { source, .. } => {
+                write!(f, "read failed: {}", source)
+            }
+        }
+    }
+}
+
+impl Error for BWSaveGameError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Decompression { source, .. } | Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
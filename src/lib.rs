@@ -0,0 +1,31 @@
+#![allow(clippy::module_inception)]
+
+pub mod bw_save_game_reader;
+pub mod bw_save_game_metadata_reader;
+pub mod bw_save_game_metadata_writer;
+pub mod bw_save_game_client_data_reader;
+pub mod bw_save_game_writer;
+pub mod bw_save_game_verifier;
+pub mod bw_save_game_appearance;
+pub mod error;
+pub mod varint;
+
+mod block_cursor;
+#[cfg(test)]
+mod test_data;
+
+pub use bw_save_game_reader::bw_save_game_reader::MAGIC;
+pub use bw_save_game_reader::structs::{BWSaveGameReader, Block, Header};
+pub use bw_save_game_metadata_reader::structs::{BWSaveGameMetadata, BWSaveGameMetadataReader, MetadataEntry};
+pub use bw_save_game_metadata_reader::enums;
+pub use bw_save_game_metadata_writer::structs::BWSaveGameMetadataWriter;
+pub use bw_save_game_client_data_reader::structs::{
+    BWSaveGameClientDataReader, BlockTwoLayout, Chunk, ChunkField, ContribEntry, ContribSection, LayoutEntry,
+    ServerChunk, ServerField, ServerSection,
+};
+pub use bw_save_game_client_data_reader::enums::{AppearanceTarget, FieldValue};
+pub use bw_save_game_writer::structs::BWSaveGameWriter;
+pub use bw_save_game_verifier::structs::{BWSaveGameVerifier, VerifyCheck, VerifyReport};
+pub use bw_save_game_appearance::structs::{AppearanceChunk, AppearancePreset, BWSaveGameAppearance};
+pub use bw_save_game_appearance::enums::AppearancePart;
+pub use error::{BWSaveGameError, ErrorBlock};
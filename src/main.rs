@@ -1,80 +1,320 @@
 use std::fs::{self, File};
 use std::error::Error;
-use std::env;
-use std::io::Write;
-use std::path::PathBuf;
+use std::process;
+use std::io::{Cursor, Read, Seek, Write};
+use std::path::{Path, PathBuf};
 
-use crate::structs::{Args, Config};
+use crate::structs::{
+    AppearanceArgs, Args, DumpBlocksArgs, DumpChunksArgs, DumpMetadataArgs, EditMetadataArgs, ExportAppearanceArgs,
+    ImportAppearanceArgs, ImportMetadataArgs, InjectAppearanceArgs, PackBlocksArgs, RestoreArgs, VerifyArgs, WriteArgs,
+};
 use clap::Parser;
-use crate::bw_save_game_metadata_reader::structs::BWSaveGameMetadataReader;
-use crate::bw_save_game_reader::structs::BWSaveGameReader;
-use crate::bw_save_game_writer::structs::BWSaveGameWriter;
-use crate::enums::Cmd;
+use dav_save_tool::{
+    AppearancePreset, AppearanceTarget, BWSaveGameClientDataReader, BWSaveGameMetadata,
+    BWSaveGameMetadataReader, BWSaveGameMetadataWriter, BWSaveGameReader, BWSaveGameVerifier, BWSaveGameWriter,
+    Block, Chunk, Header, ServerChunk, ServerField,
+};
+use crate::enums::{Character, Cmd};
+use dav_save_tool::enums::{CharacterGender, CharacterLineage};
 
 mod structs;
 mod utils;
-mod bw_save_game_reader;
-mod bw_save_game_metadata_reader;
 mod enums;
-mod bw_save_game_client_data_reader;
-mod bw_save_game_writer;
-
-fn parse_config() -> Result<Config, Box<dyn Error>> {
-    let args = Args::parse();
+mod backup;
 
+// Output folders default to the exe's folder, relative ones are taken from there too.
+fn get_out_dir(out_path: Option<&Path>) -> Result<PathBuf, Box<dyn Error>> {
     let exe_path = utils::get_exe_path()?;
-    let mut out_path = args.out_path.unwrap_or(exe_path.clone());
-
-    if !out_path.is_absolute() {
-        out_path = exe_path.join(out_path);
-    }
 
-    let config = Config {
-        in_path: args.in_path,
-        out_path,
-        command: args.command,
+    let out_dir = match out_path {
+        Some(p) if p.is_absolute() => p.to_path_buf(),
+        Some(p) => exe_path.join(p),
+        None => exe_path,
     };
 
-    Ok(config)
+    Ok(out_dir)
 }
 
-fn write_block_to_file(out_path: &PathBuf, data: &[u8]) -> Result<(), Box<dyn Error>>  {
+fn write_block_to_file(out_path: &Path, data: &[u8]) -> Result<(), Box<dyn Error>>  {
     let mut f = File::create(out_path)?;
     f.write_all(data)?;
     Ok(())
 }
 
-fn make_out_path(in_path: &PathBuf, out_path: &PathBuf, fname: &str) -> PathBuf {
-    let mut modified_in_path = in_path.clone();
+fn make_out_path(in_path: &Path, out_path: &Path, fname: &str) -> PathBuf {
+    let mut modified_in_path = in_path.to_path_buf();
     modified_in_path.set_extension(fname);
     let save_filename_no_ext = modified_in_path.file_name().unwrap();
     out_path.join(save_filename_no_ext)
 }
 
-fn inject_appearance_data(config: &Config, data: Vec<u8>) -> Result<(), Box<dyn Error>> {
-    println!(
-        "The source and dest save genders and races are assumed to match. \
-        If they don't, the dest save may get corrupted."
-    );
+fn read_save(in_path: &Path) -> Result<BWSaveGameReader<File>, Box<dyn Error>> {
+    let f = File::open(in_path)?;
+    read_save_from(f)
+}
+
+fn read_save_from<R: Read + Seek>(f: R) -> Result<BWSaveGameReader<R>, Box<dyn Error>> {
+    let mut r = BWSaveGameReader::new(f);
+
+    r.read_header()?;
+    r.read_block_one_data()?;
+    r.read_block_two_data()?;
+
+    Ok(r)
+}
+
+fn print_size_change(name: &str, old: Option<u64>, new: u64) {
+    match old {
+        Some(old) => println!("  {}: {} -> {} ({:+})", name, old, new, new as i64 - old as i64),
+        None => println!("  {}: {}", name, new),
+    }
+}
+
+// Everything a write would have done, checked against the file that's there now.
+// old_path is the save the new data was made from, which isn't always the one it would overwrite.
+fn report_dry_run(old_path: &Path, out_path: &Path, new_data: Vec<u8>) -> Result<(), Box<dyn Error>> {
+    println!("Dry run, nothing was written to {}", out_path.to_string_lossy());
+
+    let old_r = match old_path.exists() {
+        true => {
+            println!("Compared against {}", old_path.to_string_lossy());
+            Some(read_save(old_path)?)
+        }
+        false => None,
+    };
+    let new_r = read_save_from(Cursor::new(new_data.clone()))?;
+
+    let old_header = old_r.as_ref().map(|r| &r.header);
+    println!("Blocks:");
+    print_size_change("block one decompressed", old_header.map(|h| h.block_one_decomp_size), new_r.header.block_one_decomp_size);
+    print_size_change("block one compressed", old_header.map(|h| h.block_one_comp_size), new_r.header.block_one_comp_size);
+    print_size_change("block two decompressed", old_header.map(|h| h.block_two_decomp_size), new_r.header.block_two_decomp_size);
+    print_size_change("block two compressed", old_header.map(|h| h.block_two_comp_size), new_r.header.block_two_comp_size);
+
+    let read_client_data = |data: &[u8]| {
+        let mut data_r = BWSaveGameClientDataReader::new(data.to_vec());
+        data_r.read_char_data().map(|_| data_r)
+    };
+
+    let old_data_r = old_r.as_ref().and_then(|r| read_client_data(&r.block_two.data).ok());
+    match read_client_data(&new_r.block_two.data) {
+        Ok(new_data_r) => {
+            println!("Block two:");
+            print_size_change("char data", old_data_r.as_ref().map(|r| r.char_data.len() as u64), new_data_r.char_data.len() as u64);
+            print_size_change("server_offset", old_data_r.as_ref().map(|r| r.layout.server_offset() as u64), new_data_r.layout.server_offset() as u64);
+            print_size_change("contrib_offset", old_data_r.as_ref().map(|r| r.layout.contrib_offset() as u64), new_data_r.layout.contrib_offset() as u64);
+        }
+        Err(e) => println!("Block two: char data not found ({})", e),
+    }
+
+    println!("Verify:");
+    let mut v = BWSaveGameVerifier::new(new_data);
+    let report = v.verify();
+    print!("{}", report);
+
+    if !report.passed() {
+        return Err(format!("{} check(s) failed, the save would be broken", report.failed_count()).into());
+    }
+
+    Ok(())
+}
+
+// Copies the save into the backup dir before anything overwrites it.
+fn backup_save(write: &WriteArgs, save_path: &Path) -> Result<(), Box<dyn Error>> {
+    if write.dry_run || !save_path.exists() {
+        return Ok(());
+    }
+
+    // A save whose metadata doesn't parse still gets backed up, just without a description.
+    let description = read_save(save_path)
+        .and_then(|r| read_metadata(r.block_one.data))
+        .map(|meta| meta.description)
+        .unwrap_or_default();
+
+    let backup_dir = backup::get_backup_dir(save_path, write.backup_dir.as_deref());
+    let backup_path = backup::create_backup(save_path, &backup_dir, &description)?;
+
+    println!("Backed up to {}", backup_path.to_string_lossy());
+
+    Ok(())
+}
+
+fn write_save(write: &WriteArgs, old_path: &Path, out_path: &Path, header: &Header, block_one: &Block, block_two: &Block) -> Result<(), Box<dyn Error>> {
+    if write.dry_run {
+        let mut w = BWSaveGameWriter::in_memory();
+        w.write_container(header, block_one, block_two)?;
+        return report_dry_run(old_path, out_path, w.into_bytes()?);
+    }
+
+    backup_save(write, out_path)?;
+
+    let mut w = BWSaveGameWriter::new(out_path)?;
+
+    if let Err(e) = w.write_container(header, block_one, block_two) {
+        w.discard();
+        return Err(e);
+    }
+
+    w.save()?;
+
+    println!("-> {}", out_path.to_string_lossy());
+
+    Ok(())
+}
+
+// Re-parses the new block to make sure every changed field actually has an entry to land in.
+fn write_metadata(meta_r: &BWSaveGameMetadataReader, meta: &BWSaveGameMetadata) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut w = BWSaveGameMetadataWriter::new(meta_r.prefix.clone(), meta_r.entries.clone());
+    w.apply_metadata(&meta_r.metadata, meta)?;
+    let data = w.serialize()?;
+
+    let mut check_r = BWSaveGameMetadataReader::new(data.clone());
+    check_r.parse_metadata()?;
+
+    let wanted = serde_json::to_value(meta)?;
+    let written = serde_json::to_value(&check_r.metadata)?;
+
+    if let (Some(wanted), Some(written)) = (wanted.as_object(), written.as_object()) {
+        let missing: Vec<&str> = wanted.iter()
+            .filter(|(k, v)| written.get(*k) != Some(*v))
+            .map(|(k, _)| k.as_str())
+            .collect();
 
-    {
-        let mut w = BWSaveGameWriter::new()?;
-        w.rebuild(&config.out_path, data)?;
+        if !missing.is_empty() {
+            return Err(format!("these fields aren't stored in this save: {}", missing.join(", ")).into());
+        }
     }
 
-    println!("-> {}", &config.out_path.to_string_lossy());
+    Ok(data)
+}
+
+fn appearance_target(character: Character) -> AppearanceTarget {
+    match character {
+        Character::Rook => AppearanceTarget::Rook,
+        Character::Inquisitor => AppearanceTarget::Inquisitor,
+        Character::Both => AppearanceTarget::Both,
+    }
+}
+
+fn read_metadata(block_one_data: Vec<u8>) -> Result<BWSaveGameMetadata, Box<dyn Error>> {
+    let mut meta_r = BWSaveGameMetadataReader::new(block_one_data);
+    meta_r.parse_metadata()?;
+    Ok(meta_r.metadata)
+}
+
+// Appearance data for one gender or lineage doesn't fit another and can corrupt the dest save.
+fn check_appearance_compatible(src: (&CharacterLineage, &CharacterGender), dest: &BWSaveGameMetadata, force: bool) -> Result<(), Box<dyn Error>> {
+    let (src_lineage, src_gender) = src;
+    let mut mismatches = Vec::new();
+
+    if *src_gender != dest.gender {
+        mismatches.push(format!("gender: source is {:?}, dest is {:?}", src_gender, dest.gender));
+    }
+    if *src_lineage != dest.lineage {
+        mismatches.push(format!("lineage: source is {:?}, dest is {:?}", src_lineage, dest.lineage));
+    }
+
+    if mismatches.is_empty() {
+        return Ok(());
+    }
+
+    let details = mismatches.join("\n  ");
+    if !force {
+        return Err(format!(
+            "source and dest characters don't match, the dest save would likely get corrupted.\n  {}\n\
+            Use --force to inject anyway.",
+            details,
+        ).into());
+    }
+
+    println!("Injecting anyway, the dest save may get corrupted.\n  {}", details);
+
+    Ok(())
+}
+
+// Injects into dest_path and writes the result to out_path, which is often the same save.
+fn write_char_data(write: &WriteArgs, appearance: &AppearanceArgs, dest_path: &Path, out_path: &Path, char_data: &[u8]) -> Result<(), Box<dyn Error>> {
+    let target = appearance_target(appearance.character);
+
+    if write.dry_run {
+        let mut w = BWSaveGameWriter::in_memory();
+        w.write_injected(dest_path, char_data, target)?;
+        return report_dry_run(dest_path, out_path, w.into_bytes()?);
+    }
+
+    backup_save(write, out_path)?;
+
+    let w = BWSaveGameWriter::new(out_path)?;
+    w.inject_char_data(dest_path, char_data, target)?;
+
+    println!("-> {}", out_path.to_string_lossy());
+
+    Ok(())
+}
 
-    let temp_path = env::temp_dir().join("davst_temp.bin");
-    fs::remove_file(temp_path)?;
+fn inject_appearance_data(args: &InjectAppearanceArgs) -> Result<(), Box<dyn Error>> {
+    let src_r = read_save(&args.source)?;
+    let dest_r = read_save(&args.dest)?;
+
+    let src_meta = read_metadata(src_r.block_one.data)?;
+    let dest_meta = read_metadata(dest_r.block_one.data)?;
+    check_appearance_compatible((&src_meta.lineage, &src_meta.gender), &dest_meta, args.appearance.force)?;
+
+    let mut src_data_r = BWSaveGameClientDataReader::new(src_r.block_two.data);
+    src_data_r.read_char_data()?;
+
+    let out_path = args.output.as_ref().unwrap_or(&args.dest);
+    write_char_data(&args.write, &args.appearance, &args.dest, out_path, &src_data_r.char_data)
+}
+
+fn export_appearance(args: &ExportAppearanceArgs) -> Result<(), Box<dyn Error>> {
+    let r = read_save(&args.in_path)?;
+
+    let mut meta_r = BWSaveGameMetadataReader::new(r.block_one.data);
+    meta_r.parse_metadata()?;
+
+    let mut data_r = BWSaveGameClientDataReader::new(r.block_two.data);
+    data_r.read_char_data()?;
+
+    let preset = AppearancePreset::new(&meta_r.metadata, &data_r.char_data);
+    if preset.appearance.is_none() {
+        println!("Character data couldn't be decoded, the preset only holds it raw.");
+    }
+
+    let out_path = make_out_path(&args.in_path, &get_out_dir(args.out_path.as_deref())?, "appearance.json");
+    let json_data = preset.to_json()?;
+
+    let mut f = File::create(&out_path)?;
+    f.write_all(json_data.as_bytes())?;
+
+    println!("-> {}", out_path.to_string_lossy());
 
     Ok(())
 }
 
-fn dump_metadata(config: &Config, data: Vec<u8>) -> Result<(), Box<dyn Error>> {
+// Takes --dest and --output the same way inject-appearance does.
+fn import_appearance(args: &ImportAppearanceArgs) -> Result<(), Box<dyn Error>> {
+    let json_data = fs::read_to_string(&args.in_path)?;
+    let preset = AppearancePreset::from_json(&json_data)?;
+
+    let dest_meta = read_metadata(read_save(&args.dest)?.block_one.data)?;
+    check_appearance_compatible((&preset.lineage, &preset.gender), &dest_meta, args.appearance.force)?;
+
+    let char_data = preset.import_char_data()?;
+    if char_data != preset.char_data {
+        println!("The preset's appearance was edited, importing the edited version.");
+    }
+
+    let out_path = args.output.as_ref().unwrap_or(&args.dest);
+    write_char_data(&args.write, &args.appearance, &args.dest, out_path, &char_data)
+}
+
+fn dump_metadata(args: &DumpMetadataArgs) -> Result<(), Box<dyn Error>> {
+    let data = read_save(&args.in_path)?.block_one.data;
     let mut r = BWSaveGameMetadataReader::new(data);
     r.parse_metadata()?;
 
-    let out_path = make_out_path(&config.in_path, &config.out_path, "metadata.json");
+    let out_path = make_out_path(&args.in_path, &get_out_dir(args.out_path.as_deref())?, "metadata.json");
     let json_data = serde_json::to_string_pretty(&r.metadata)?;
 
     let mut f = File::create(&out_path)?;
@@ -86,11 +326,13 @@ fn dump_metadata(config: &Config, data: Vec<u8>) -> Result<(), Box<dyn Error>> {
 }
 
 
-fn dump_blocks(config: &Config, r: BWSaveGameReader<File>) -> Result<(), Box<dyn Error>>  {
-    let block_one_out_path = make_out_path(&config.in_path, &config.out_path, "block_one.bin");
-    write_block_to_file(&block_one_out_path, &r.block_one_data)?;
-    let block_two_out_path = make_out_path(&config.in_path, &config.out_path, "block_two.bin");
-    write_block_to_file(&block_two_out_path, &r.block_two_data)?;
+fn dump_blocks(args: &DumpBlocksArgs) -> Result<(), Box<dyn Error>>  {
+    let r = read_save(&args.in_path)?;
+    let out_dir = get_out_dir(args.out_path.as_deref())?;
+    let block_one_out_path = make_out_path(&args.in_path, &out_dir, "block_one.bin");
+    write_block_to_file(&block_one_out_path, &r.block_one.data)?;
+    let block_two_out_path = make_out_path(&args.in_path, &out_dir, "block_two.bin");
+    write_block_to_file(&block_two_out_path, &r.block_two.data)?;
 
     println!("-> {}", block_one_out_path.to_string_lossy());
     println!("-> {}", block_two_out_path.to_string_lossy());
@@ -98,31 +340,405 @@ fn dump_blocks(config: &Config, r: BWSaveGameReader<File>) -> Result<(), Box<dyn
     Ok(())
 }
 
+// Expects the block one file from dump-blocks, the block two file is picked up next to it.
+// The save is named after the block one file, minus the suffix dump-blocks gives it.
+fn pack_blocks(args: &PackBlocksArgs) -> Result<(), Box<dyn Error>> {
+    let block_one_fname = args.in_path.file_name()
+        .and_then(|n| n.to_str())
+        .ok_or("bad block one path")?;
+    let save_fname = block_one_fname.strip_suffix(".block_one.bin");
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let config = parse_config()
-        .expect("failed to parse args");
-    // fs::create_dir_all(&config.out_path)?;
+    let block_two_path = match (&args.block_two_path, save_fname) {
+        (Some(path), _) => path.clone(),
+        (None, Some(save_fname)) => args.in_path.with_file_name(format!("{}.block_two.bin", save_fname)),
+        (None, None) => return Err("block one path doesn't end with .block_one.bin, pass --block-two".into()),
+    };
+    let save_fname = match save_fname {
+        Some(save_fname) => save_fname.to_string(),
+        None => args.in_path.file_stem().unwrap_or_default().to_string_lossy().to_string(),
+    };
 
-    let f = File::open(&config.in_path)?;
-    let mut r = BWSaveGameReader::new(f);
+    let block_one_data = fs::read(&args.in_path)?;
+    let block_two_data = fs::read(&block_two_path)?;
 
-    r.read_header()?;
-    r.read_block_one_data()?;
-    r.read_block_two_data()?;
+    // Without an original save there's nothing to copy the unknown header fields from.
+    let (header, block_one, block_two) = match &args.header_path {
+        Some(header_path) => {
+            let r = read_save(header_path)?;
+            let block_one = Block { data: block_one_data, ..r.block_one };
+            let block_two = Block { data: block_two_data, ..r.block_two };
+            (r.header, block_one, block_two)
+        }
+        None => {
+            let header = Header { version: 0x02, ..Header::default() };
+            let block_one = Block { data: block_one_data, ..Block::default() };
+            let block_two = Block { data: block_two_data, ..Block::default() };
+            (header, block_one, block_two)
+        }
+    };
+
+    let out_path = get_out_dir(args.out_path.as_deref())?.join(format!("{}.csav", save_fname));
+    let old_path = args.header_path.as_deref().unwrap_or(&out_path);
+    write_save(&args.write, old_path, &out_path, &header, &block_one, &block_two)?;
+
+    Ok(())
+}
+
+fn edit_metadata(args: &EditMetadataArgs) -> Result<(), Box<dyn Error>> {
+    let r = read_save(&args.in_path)?;
+
+    let mut meta_r = BWSaveGameMetadataReader::new(r.block_one.data.clone());
+    meta_r.parse_metadata()?;
+
+    let mut meta = meta_r.metadata.clone();
+    for assignment in &args.set {
+        let (field, value) = assignment.split_once('=')
+            .ok_or(format!("expected field=value, got {}", assignment))?;
+        meta.set_field(field.trim(), value)?;
+    }
+
+    let block_one = Block {
+        data: write_metadata(&meta_r, &meta)?,
+        ..r.block_one
+    };
+
+    let out_path = make_out_path(&args.in_path, &get_out_dir(args.out_path.as_deref())?, "csav");
+    write_save(&args.write, &args.in_path, &out_path, &r.header, &block_one, &r.block_two)?;
+
+    Ok(())
+}
+
+// Takes --dest and --output the same way inject-appearance does.
+fn import_metadata(args: &ImportMetadataArgs) -> Result<(), Box<dyn Error>> {
+    let json_data = fs::read_to_string(&args.in_path)?;
+    let meta: BWSaveGameMetadata = serde_json::from_str(&json_data)?;
 
+    let r = read_save(&args.dest)?;
 
-    let res = match config.command {
-        Cmd::DumpBlocks | Cmd::Db => dump_blocks(&config, r),
-        Cmd::DumpMetadata | Cmd::Dm => dump_metadata(&config, r.block_one_data),
-        Cmd::InjectAppearance | Cmd::Ia => inject_appearance_data(&config, r.block_two_data),
+    let mut meta_r = BWSaveGameMetadataReader::new(r.block_one.data.clone());
+    meta_r.parse_metadata()?;
+
+    let block_one = Block {
+        data: write_metadata(&meta_r, &meta)?,
+        ..r.block_one
     };
 
-    if let Err(e) = res {
-        println!("Command failed.\n{:?}", e);
+    let out_path = args.output.as_ref().unwrap_or(&args.dest);
+    write_save(&args.write, &args.dest, out_path, &r.header, &block_one, &r.block_two)?;
+
+    Ok(())
+}
+
+fn verify(args: &VerifyArgs) -> Result<(), Box<dyn Error>> {
+    let data = fs::read(&args.in_path)?;
+
+    let mut v = BWSaveGameVerifier::new(data);
+    let report = v.verify();
+    print!("{}", report);
+
+    if !report.passed() {
+        return Err(format!("{} check(s) failed", report.failed_count()).into());
+    }
+
+    Ok(())
+}
+
+// Number of payload bytes shown per chunk.
+const CHUNK_PREVIEW_LEN: usize = 16;
+
+fn print_chunk(chunk: &Chunk, data: &[u8]) {
+    let payload = chunk.payload(data);
+    let preview = &payload[..payload.len().min(CHUNK_PREVIEW_LEN)];
+
+    println!(
+        "{}{:#08X} {:#04X} {} size={} depth={} fields={} [{}]",
+        "  ".repeat(chunk.depth),
+        chunk.offset,
+        chunk.marker,
+        chunk.name.as_deref().unwrap_or("-"),
+        chunk.size,
+        chunk.depth,
+        chunk.fields.len(),
+        utils::to_hex(preview),
+    );
+
+    for child in &chunk.children {
+        print_chunk(child, data);
+    }
+}
+
+fn chunk_to_json(chunk: &Chunk, data: &[u8]) -> serde_json::Value {
+    let payload = chunk.payload(data);
+    let preview = &payload[..payload.len().min(CHUNK_PREVIEW_LEN)];
+
+    let children: Vec<serde_json::Value> = chunk.children.iter()
+        .map(|c| chunk_to_json(c, data))
+        .collect();
+
+    let fields: Vec<serde_json::Value> = chunk.fields.iter()
+        .map(|f| serde_json::json!({
+            "offset": f.offset,
+            "tag": f.tag,
+            "name": f.name,
+            "value_offset": f.value_offset,
+            "value_len": f.value_len,
+        }))
+        .collect();
+
+    serde_json::json!({
+        "offset": chunk.offset,
+        "marker": chunk.marker,
+        "name": chunk.name,
+        "size": chunk.size,
+        "size_len": chunk.size_len,
+        "data_offset": chunk.data_offset,
+        "depth": chunk.depth,
+        "preview": utils::to_hex(preview),
+        "fields": fields,
+        "children": children,
+    })
+}
+
+fn print_server_fields(fields: &[ServerField], depth: usize) {
+    for field in fields {
+        println!(
+            "{}{:#08X} {} = {}",
+            "  ".repeat(depth),
+            field.offset,
+            field.name.as_deref().unwrap_or("-"),
+            field.value,
+        );
+    }
+}
+
+fn print_server_chunk(chunk: &ServerChunk, depth: usize) {
+    println!(
+        "{}{:#08X} {} size={}",
+        "  ".repeat(depth),
+        chunk.offset,
+        chunk.name.as_deref().unwrap_or("-"),
+        chunk.size,
+    );
+
+    if let Some(raw) = &chunk.raw {
+        println!(
+            "{}raw [{}]",
+            "  ".repeat(depth + 1),
+            utils::to_hex(&raw[..raw.len().min(CHUNK_PREVIEW_LEN)]),
+        );
+    }
+
+    print_server_fields(&chunk.fields, depth + 1);
+    for child in &chunk.chunks {
+        print_server_chunk(child, depth + 1);
+    }
+}
+
+fn dump_chunks(args: &DumpChunksArgs) -> Result<(), Box<dyn Error>> {
+    let data = read_save(&args.in_path)?.block_two.data;
+    let mut r = BWSaveGameClientDataReader::new(data.clone());
+    r.read_chunk_tree()?;
+
+    // The tree is dumped for any block two, the sections only where it has the server layout.
+    let server = r.read_server_section().map(|_| r.server.clone());
+    let contrib = r.read_contrib_section().map(|_| r.contrib.clone());
+
+    if !args.json && args.contrib {
+        let contrib = contrib?;
+        println!("contrib section at {:#08X} size={}", contrib.offset, contrib.size);
+
+        for entry in &contrib.entries {
+            println!(
+                "  [{}] {:#08X} size={} fields={} chunks={}{}",
+                entry.index,
+                entry.chunk.offset,
+                entry.chunk.size,
+                entry.chunk.fields.len(),
+                entry.chunk.chunks.len(),
+                if entry.is_char_data { " (character data)" } else { "" },
+            );
+
+            if !entry.is_char_data {
+                print_server_fields(&entry.chunk.fields, 2);
+            }
+        }
+
+        return Ok(());
+    }
+
+    if !args.json {
+        for chunk in &r.chunks {
+            print_chunk(chunk, &data);
+        }
+
+        println!();
+        match &server {
+            Ok(server) => {
+                println!("server section at {:#08X} size={}", server.offset, server.size);
+                print_server_fields(&server.fields, 1);
+                for chunk in &server.chunks {
+                    print_server_chunk(chunk, 1);
+                }
+            }
+            Err(e) => println!("Skipped the server section: {}", e),
+        }
+
+        return Ok(());
+    }
+
+    let chunks: Vec<serde_json::Value> = r.chunks.iter()
+        .map(|c| chunk_to_json(c, &data))
+        .collect();
+
+    for (name, res) in [("server", server.as_ref().err()), ("contrib", contrib.as_ref().err())] {
+        if let Some(e) = res {
+            println!("Skipped the {} section: {}", name, e);
+        }
+    }
+
+    let out_path = make_out_path(&args.in_path, &get_out_dir(args.out_path.as_deref())?, "chunks.json");
+    let json_data = serde_json::to_string_pretty(&serde_json::json!({
+        "chunks": chunks,
+        "server": server.ok(),
+        "contrib": contrib.ok(),
+    }))?;
+
+    let mut f = File::create(&out_path)?;
+    f.write_all(json_data.as_bytes())?;
+
+    println!("-> {}", out_path.to_string_lossy());
+
+    Ok(())
+}
+
+// Lists the backups of -i, or puts the one picked with --index back.
+fn restore(args: &RestoreArgs) -> Result<(), Box<dyn Error>> {
+    let backup_dir = backup::get_backup_dir(&args.in_path, args.write.backup_dir.as_deref());
+    let backups = backup::list_backups(&args.in_path, &backup_dir)?;
+
+    if backups.is_empty() {
+        return Err(format!("no backups of this save in {}", backup_dir.to_string_lossy()).into());
+    }
+
+    let Some(index) = args.index else {
+        for (i, backup_path) in backups.iter().enumerate() {
+            println!("{:>3}  {}", i + 1, backup_path.file_name().unwrap_or_default().to_string_lossy());
+        }
+        println!("Pass --index to restore one of these.");
+        return Ok(());
+    };
+
+    let backup_path = index.checked_sub(1)
+        .and_then(|i| backups.get(i))
+        .ok_or(format!("--index must be between 1 and {}", backups.len()))?;
+
+    // Goes through the reader and writer so a broken backup can't replace the save.
+    let r = read_save(backup_path)?;
+    println!("Restoring {}", backup_path.to_string_lossy());
+    write_save(&args.write, &args.in_path, &args.in_path, &r.header, &r.block_one, &r.block_two)?;
+
+    Ok(())
+}
+
+fn run(command: &Cmd) -> Result<(), Box<dyn Error>> {
+    match command {
+        Cmd::DumpBlocks(args) => dump_blocks(args),
+        Cmd::DumpMetadata(args) => dump_metadata(args),
+        Cmd::InjectAppearance(args) => inject_appearance_data(args),
+        Cmd::PackBlocks(args) => pack_blocks(args),
+        Cmd::Verify(args) => verify(args),
+        Cmd::EditMetadata(args) => edit_metadata(args),
+        Cmd::ImportMetadata(args) => import_metadata(args),
+        Cmd::DumpChunks(args) => dump_chunks(args),
+        Cmd::ExportAppearance(args) => export_appearance(args),
+        Cmd::ImportAppearance(args) => import_appearance(args),
+        Cmd::Restore(args) => restore(args),
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    if let Err(e) = run(&args.command) {
+        println!("Command failed.\n{}", e);
+        process::exit(1);
     } else {
         println!("OK.");
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use dav_save_tool::{AppearanceChunk, AppearancePart, BWSaveGameAppearance};
+    use uuid::Uuid;
+    use super::*;
+
+    fn object(marker: u8, name: Option<&str>, parts: Vec<AppearancePart>) -> AppearancePart {
+        AppearancePart::Chunk(AppearanceChunk { marker, name: name.map(str::to_string), size_len: 1, parts })
+    }
+
+    fn record(value: f32) -> AppearancePart {
+        object(0x82, None, vec![AppearancePart::MorphSlider { tag: 0x0B, name: "morph_slider_0".to_string(), value }])
+    }
+
+    // Root holding "server", which holds the contribution list with the character data third.
+    fn write_save(path: &Path, char_data: AppearancePart) {
+        let contrib = object(0x01, Some("contribution"), vec![object(0x82, None, vec![]), object(0x82, None, vec![]), char_data]);
+        let AppearancePart::Chunk(root) = object(0x82, None, vec![object(0x02, Some("server"), vec![contrib])]) else {
+            unreachable!();
+        };
+
+        let mut w = BWSaveGameWriter::in_memory();
+        let header = Header { version: 0x02, ..Header::default() };
+        let block_one = Block { data: vec![0x82, 0x00], ..Block::default() };
+        let block_two = Block { data: root.encode(), ..Block::default() };
+        w.write_container(&header, &block_one, &block_two).unwrap();
+        fs::write(path, w.into_bytes().unwrap()).unwrap();
+    }
+
+    fn sliders(path: &Path) -> Vec<f32> {
+        let mut data_r = BWSaveGameClientDataReader::new(read_save(path).unwrap().block_two.data);
+        data_r.read_char_data().unwrap();
+
+        let appearance = BWSaveGameAppearance::from_char_data(&data_r.char_data).unwrap();
+        appearance.root.parts.iter()
+            .map(|record| match record {
+                AppearancePart::Chunk(AppearanceChunk { parts, .. }) => match parts[0] {
+                    AppearancePart::MorphSlider { value, .. } => value,
+                    _ => panic!("slider wasn't picked out"),
+                },
+                _ => panic!("record wasn't walked"),
+            })
+            .collect()
+    }
+
+    fn run_args(args: &[&Path]) -> Result<(), Box<dyn Error>> {
+        let args = Args::parse_from(["davst"].into_iter().map(Path::new).chain(args.iter().copied()));
+        run(&args.command)
+    }
+
+    #[test]
+    fn slider_edited_in_an_exported_preset_is_imported() {
+        let dir = env::temp_dir().join(format!("davst-cli-{}", Uuid::new_v4()));
+        fs::create_dir(&dir).unwrap();
+        let (src, dest, out) = (dir.join("src.csav"), dir.join("dest.csav"), dir.join("out.csav"));
+        write_save(&src, object(0x82, None, vec![record(0.25), record(0.5)]));
+        write_save(&dest, object(0x82, None, vec![record(0.0), record(0.0)]));
+
+        run_args(&[Path::new("ea"), Path::new("-i"), &src, Path::new("-o"), &dir]).unwrap();
+
+        let preset_path = dir.join("src.appearance.json");
+        let mut preset: serde_json::Value = serde_json::from_str(&fs::read_to_string(&preset_path).unwrap()).unwrap();
+        preset["appearance"]["root"]["parts"][1]["Chunk"]["parts"][0]["MorphSlider"]["value"] = 0.75.into();
+        fs::write(&preset_path, preset.to_string()).unwrap();
+
+        run_args(&[Path::new("iap"), Path::new("-i"), &preset_path, Path::new("--dest"), &dest, Path::new("--output"), &out]).unwrap();
+
+        assert_eq!(sliders(&out), [0.25, 0.75]);
+        assert_eq!(sliders(&dest), [0.0, 0.0]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,22 +1,174 @@
 use std::path::PathBuf;
-use clap::Parser;
-use crate::enums::Cmd;
+use clap::{Args as ClapArgs, Parser};
+use crate::enums::{Character, Cmd};
 
 #[derive(Parser)]
 #[command(name = "DAV save tool")]
 pub struct Args {
-    #[clap(short, long, help="Input path.", required=true)]
+    #[command(subcommand)]
+    pub command: Cmd,
+}
+
+// Shared by every command that overwrites or creates a save.
+#[derive(ClapArgs, Clone, Debug)]
+pub struct WriteArgs {
+    #[clap(long, help="Where to keep backups of saves before they're overwritten. Defaults to a davst_backups folder next to the save.")]
+    pub backup_dir: Option<PathBuf>,
+
+    #[clap(long, help="Do everything in memory and report what would change, without writing.")]
+    pub dry_run: bool,
+}
+
+// Shared by inject-appearance and import-appearance.
+#[derive(ClapArgs, Clone, Debug)]
+pub struct AppearanceArgs {
+    #[clap(long, value_enum, default_value_t, help="Whose appearance to carry over.")]
+    pub character: Character,
+
+    #[clap(long, help="Inject even if the gender or lineage doesn't match.")]
+    pub force: bool,
+}
+
+#[derive(ClapArgs, Clone, Debug)]
+pub struct DumpBlocksArgs {
+    #[clap(short, long, help="Save file.")]
     pub in_path: PathBuf,
 
-    #[clap(short, long, help="Output path.")]
+    #[clap(short, long, help="Output folder. Defaults to the folder davst is in.")]
     pub out_path: Option<PathBuf>,
+}
 
-    #[arg(value_enum, help="Command.")]
-    pub command: Cmd,
+#[derive(ClapArgs, Clone, Debug)]
+pub struct DumpMetadataArgs {
+    #[clap(short, long, help="Save file.")]
+    pub in_path: PathBuf,
+
+    #[clap(short, long, help="Output folder. Defaults to the folder davst is in.")]
+    pub out_path: Option<PathBuf>,
 }
 
-pub struct Config {
+#[derive(ClapArgs, Clone, Debug)]
+pub struct InjectAppearanceArgs {
+    #[clap(long, help="Save to take the appearance from.")]
+    pub source: PathBuf,
+
+    #[clap(long, help="Save to inject the appearance into.")]
+    pub dest: PathBuf,
+
+    #[clap(long, help="Where to write the injected save. Defaults to overwriting the dest save.")]
+    pub output: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub appearance: AppearanceArgs,
+
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(ClapArgs, Clone, Debug)]
+pub struct PackBlocksArgs {
+    #[clap(short, long, help="Block one file written by dump-blocks.")]
     pub in_path: PathBuf,
-    pub out_path: PathBuf,
-    pub command: Cmd,
+
+    #[clap(long = "block-two", help="Block two file. Defaults to the .block_two.bin file next to the block one file.")]
+    pub block_two_path: Option<PathBuf>,
+
+    #[clap(short, long, help="Output folder. Defaults to the folder davst is in.")]
+    pub out_path: Option<PathBuf>,
+
+    #[clap(long, help="Save file to copy the container header from.")]
+    pub header_path: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(ClapArgs, Clone, Debug)]
+pub struct VerifyArgs {
+    #[clap(short, long, help="Save file.")]
+    pub in_path: PathBuf,
+}
+
+#[derive(ClapArgs, Clone, Debug)]
+pub struct EditMetadataArgs {
+    #[clap(short, long, help="Save file.")]
+    pub in_path: PathBuf,
+
+    #[clap(short, long, help="Output folder. Defaults to the folder davst is in.")]
+    pub out_path: Option<PathBuf>,
+
+    #[clap(long, required=true, help="Metadata field to change, e.g. --set difficulty=Keeper. Can be repeated.")]
+    pub set: Vec<String>,
+
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(ClapArgs, Clone, Debug)]
+pub struct ImportMetadataArgs {
+    #[clap(short, long, help="Metadata JSON file written by dump-metadata.")]
+    pub in_path: PathBuf,
+
+    #[clap(long, help="Save to write the metadata into.")]
+    pub dest: PathBuf,
+
+    #[clap(long, help="Where to write the result. Defaults to overwriting the dest save.")]
+    pub output: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(ClapArgs, Clone, Debug)]
+pub struct DumpChunksArgs {
+    #[clap(short, long, help="Save file.")]
+    pub in_path: PathBuf,
+
+    #[clap(short, long, help="Output folder for --json. Defaults to the folder davst is in.")]
+    pub out_path: Option<PathBuf>,
+
+    #[clap(long, help="Write JSON instead of printing.")]
+    pub json: bool,
+
+    #[clap(long, help="Only print the contribution list.")]
+    pub contrib: bool,
+}
+
+#[derive(ClapArgs, Clone, Debug)]
+pub struct ExportAppearanceArgs {
+    #[clap(short, long, help="Save file.")]
+    pub in_path: PathBuf,
+
+    #[clap(short, long, help="Output folder. Defaults to the folder davst is in.")]
+    pub out_path: Option<PathBuf>,
+}
+
+#[derive(ClapArgs, Clone, Debug)]
+pub struct ImportAppearanceArgs {
+    #[clap(short, long, help="Preset file written by export-appearance.")]
+    pub in_path: PathBuf,
+
+    #[clap(long, help="Save to inject the appearance into.")]
+    pub dest: PathBuf,
+
+    #[clap(long, help="Where to write the injected save. Defaults to overwriting the dest save.")]
+    pub output: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub appearance: AppearanceArgs,
+
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(ClapArgs, Clone, Debug)]
+pub struct RestoreArgs {
+    #[clap(short, long, help="Save file.")]
+    pub in_path: PathBuf,
+
+    #[clap(long, help="Backup to put back, as numbered by the list.")]
+    pub index: Option<usize>,
+
+    #[command(flatten)]
+    pub write: WriteArgs,
 }
@@ -0,0 +1,90 @@
+// Builders for synthetic block data in the same layout as real saves, shared by the unit tests.
+use crate::varint;
+
+pub(crate) fn field(tag: u8, name: &str, value: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![tag];
+    bytes.extend_from_slice(name.as_bytes());
+    bytes.push(0x00);
+    bytes.extend_from_slice(value);
+    bytes
+}
+
+pub(crate) fn string_value(s: &str) -> Vec<u8> {
+    let mut bytes = varint::encode(s.len() as u32 + 1);
+    bytes.extend_from_slice(s.as_bytes());
+    bytes.push(0x00);
+    bytes
+}
+
+// Block one's object, the stored size being the body length plus size_slack, which the game
+// doesn't always keep equal, written at width bytes.
+pub(crate) fn block_one(entries: &[Vec<u8>], size_slack: u32, width: usize) -> Vec<u8> {
+    let body = entries.concat();
+    let mut bytes = vec![0x82];
+    bytes.extend_from_slice(&varint::encode_padded(body.len() as u32 + size_slack, width));
+    bytes.extend_from_slice(&body);
+    bytes
+}
+
+pub(crate) fn metadata_entries() -> Vec<Vec<u8>> {
+    vec![
+        field(0x08, "level", &6u32.to_le_bytes()),
+        field(0x07, "description", &string_value("Save 10")),
+        field(0x08, "difficulty", &1u32.to_le_bytes()),
+        field(0x08, "newpatchkey", &7u32.to_le_bytes()),
+    ]
+}
+
+// A list or object with content plus its end byte, the size written at width bytes.
+pub(crate) fn chunk(marker: u8, name: Option<&str>, content: &[u8], width: usize) -> Vec<u8> {
+    let mut body = content.to_vec();
+    body.push(0x00);
+
+    let mut bytes = vec![marker];
+    if let Some(name) = name {
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.push(0x00);
+    }
+    bytes.extend_from_slice(&varint::encode_padded(body.len() as u32, width));
+    bytes.extend_from_slice(&body);
+    bytes
+}
+
+pub(crate) fn appearance_record(name: Option<&str>, seed: u32, sliders: usize) -> Vec<u8> {
+    let mut content = Vec::new();
+    for i in 0..sliders {
+        let value = ((seed as usize * 7 + i) % 100) as f32 / 100.0;
+        content.extend(field(0x0B, &format!("morph_slider_{}", i), &value.to_le_bytes()));
+    }
+    content.extend(field(0x08, "skin_color", &(0xFF8040 + seed).to_le_bytes()));
+    content.extend(field(0x0F, "hair_asset", &[seed as u8; 16]));
+    content.extend(field(0x0F, "tattoo_asset", &[seed as u8 + 1; 16]));
+    content.extend(field(0x08, "scar_index", &seed.to_le_bytes()));
+
+    let marker = if name.is_some() { 0x02 } else { 0x82 };
+    chunk(marker, name, &content, 1)
+}
+
+pub(crate) fn char_data(seed: u32, sliders: usize, width: usize) -> Vec<u8> {
+    let records = [
+        appearance_record(None, seed, sliders),
+        appearance_record(None, seed + 100, sliders),
+    ].concat();
+    chunk(0x82, None, &records, width)
+}
+
+// Root holding "server", which holds the contribution list with the character data third.
+pub(crate) fn block_two(char_data: &[u8], width: usize) -> Vec<u8> {
+    let quest = chunk(0x82, None, &[
+        field(0x08, "questflag", &1u32.to_le_bytes()),
+        field(0x07, "zone", &string_value("Minrathous")),
+    ].concat(), 2);
+    let gold = chunk(0x82, None, &field(0x09, "gold", &1234i64.to_le_bytes()), 2);
+    let done = chunk(0x82, None, &field(0x06, "done", &[0x01]), 1);
+
+    let contrib = chunk(0x01, Some("contribution"), &[quest, gold, char_data.to_vec(), done].concat(), width);
+    let server = chunk(0x02, Some("server"), &contrib, width);
+    let client = chunk(0x02, Some("client"), &field(0x08, "clientthing", &5u32.to_le_bytes()), 1);
+
+    chunk(0x82, None, &[server, client].concat(), 3)
+}
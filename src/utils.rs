@@ -8,4 +8,11 @@ pub fn get_exe_path() -> Result<PathBuf, Box<dyn Error>> {
         .ok_or("failed to get path of executable")?;
     let exe_path_buf = PathBuf::from(parent_dir);
     Ok(exe_path_buf)
+}
+
+pub fn to_hex(data: &[u8]) -> String {
+    data.iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<String>>()
+        .join(" ")
 }
\ No newline at end of file
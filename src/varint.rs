@@ -0,0 +1,147 @@
+// LEB128 varints, used for every size and string length in both blocks.
+
+// A u32 never needs more than 5 bytes.
+pub const MAX_LEN: usize = 5;
+
+pub fn encode(value: u32) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut value = value;
+
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+
+        if value > 0 {
+            bytes.push(byte | 0x80);
+        } else {
+            bytes.push(byte);
+            break;
+        }
+    }
+
+    bytes
+}
+
+pub fn encoded_len(value: u32) -> usize {
+    let mut len = 1;
+    let mut value = value >> 7;
+
+    while value > 0 {
+        len += 1;
+        value >>= 7;
+    }
+
+    len
+}
+
+// Padded with continuation bytes to at least width, the game writes some sizes at a fixed width.
+pub fn encode_padded(value: u32, width: usize) -> Vec<u8> {
+    let mut bytes = encode(value);
+    while bytes.len() < width.min(MAX_LEN) {
+        *bytes.last_mut().unwrap() |= 0x80;
+        bytes.push(0x00);
+    }
+
+    bytes
+}
+
+// Value and number of bytes read, None if the varint is cut off or too long for a u32.
+pub fn decode(bytes: &[u8]) -> Option<(u32, usize)> {
+    let mut value = 0u32;
+    let mut shift = 0;
+
+    for (i, &byte) in bytes.iter().take(MAX_LEN).enumerate() {
+        value |= ((byte & 0x7F) as u32) << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+
+    None
+}
+
+// Replaces the varint at offset, keeping its width when the value fits so padded sizes stay
+// padded, and moving everything after it only when it has to grow. Returns the change in
+// width so callers can fix up any sizes that include it.
+pub fn rewrite(data: &mut Vec<u8>, offset: usize, value: u32) -> Option<i64> {
+    let (_, old_len) = decode(data.get(offset..)?)?;
+    let bytes = encode_padded(value, old_len);
+    let width_delta = bytes.len() as i64 - old_len as i64;
+
+    data.splice(offset..offset + old_len, bytes);
+
+    Some(width_delta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_at_every_width() {
+        let cases: [(u32, &[u8]); 6] = [
+            (0, &[0x00]),
+            (127, &[0x7F]),
+            (128, &[0x80, 0x01]),
+            (300, &[0xAC, 0x02]),
+            (16_384, &[0x80, 0x80, 0x01]),
+            (u32::MAX, &[0xFF, 0xFF, 0xFF, 0xFF, 0x0F]),
+        ];
+
+        for (value, bytes) in cases {
+            assert_eq!(encode(value), bytes);
+            assert_eq!(encoded_len(value), bytes.len());
+            assert_eq!(decode(bytes), Some((value, bytes.len())));
+        }
+    }
+
+    #[test]
+    fn padded_varints_decode_to_the_same_value() {
+        assert_eq!(encode_padded(5, 3), [0x85, 0x80, 0x00]);
+        assert_eq!(decode(&encode_padded(5, 3)), Some((5, 3)));
+        assert_eq!(encode_padded(300, 1), [0xAC, 0x02]);
+        assert_eq!(encode_padded(1, 9).len(), MAX_LEN);
+    }
+
+    #[test]
+    fn decode_stops_at_the_first_byte_without_the_high_bit() {
+        assert_eq!(decode(&[0xAC, 0x02, 0xFF]), Some((300, 2)));
+    }
+
+    #[test]
+    fn cut_off_or_overlong_varints_dont_decode() {
+        assert_eq!(decode(&[]), None);
+        assert_eq!(decode(&[0x80, 0x80]), None);
+        assert_eq!(decode(&[0x80; MAX_LEN + 1]), None);
+    }
+
+    #[test]
+    fn rewrite_moves_the_rest_when_the_width_grows() {
+        let mut data = vec![0xAA, 0x7F, 0xBB];
+        assert_eq!(rewrite(&mut data, 1, 128), Some(1));
+        assert_eq!(data, [0xAA, 0x80, 0x01, 0xBB]);
+
+        assert_eq!(rewrite(&mut data, 1, 5), Some(0));
+        assert_eq!(data, [0xAA, 0x85, 0x00, 0xBB]);
+    }
+
+    #[test]
+    fn rewrite_keeps_a_padded_varint_padded() {
+        let mut data = vec![0x85, 0x80, 0x00, 0xBB];
+        assert_eq!(rewrite(&mut data, 0, 6), Some(0));
+        assert_eq!(data, [0x86, 0x80, 0x00, 0xBB]);
+
+        assert_eq!(rewrite(&mut data, 0, 20_000), Some(0));
+        assert_eq!(decode(&data), Some((20_000, 3)));
+    }
+
+    #[test]
+    fn rewrite_fails_on_a_bad_offset() {
+        let mut data = vec![0x80];
+        assert_eq!(rewrite(&mut data, 0, 1), None);
+        assert_eq!(rewrite(&mut data, 5, 1), None);
+        assert_eq!(data, [0x80]);
+    }
+}